@@ -0,0 +1,85 @@
+// replaces the mention being typed at the end of the message with the picked username
+function completeMention(textarea, username) {
+    const start = textarea.value.lastIndexOf("@");
+    textarea.value = textarea.value.slice(0, start) + "@" + username + " ";
+    textarea.focus();
+    document.getElementById("mention-suggestions").innerHTML = "";
+}
+
+// empties the message input once it was sent, the chat window isn't rendered again for new messages
+function clearComposer(form, event) {
+    // requests from inside the form, like mention suggestions, bubble up here too
+    if (event.detail.elt !== form || !event.detail.successful) {
+        return;
+    }
+    form.querySelector("#message-input").value = "";
+    form.querySelector("input[type=file]").value = "";
+}
+
+// subscribes this browser to push messages for when no page is open
+async function enablePush(status) {
+    const response = await fetch("/api/notifications/push/public_key");
+    if (!response.ok || !("serviceWorker" in navigator)) {
+        status.textContent = "Push notifications aren't available.";
+        return;
+    }
+
+    try {
+        const registration = await navigator.serviceWorker.register("/assets/sw.js");
+        const applicationServerKey = await response.text();
+        const subscribe = async () => {
+            const subscription = await registration.pushManager.subscribe({
+                userVisibleOnly: true,
+                applicationServerKey,
+            });
+            const saved = await fetch("/api/notifications/push/subscribe", {
+                method: "POST",
+                headers: { "Content-Type": "application/json" },
+                body: JSON.stringify(subscription),
+            });
+            return { subscription, saved };
+        };
+
+        let { subscription, saved } = await subscribe();
+        // someone else used push on this browser before, their endpoint stays theirs
+        if (saved.status === 409) {
+            await subscription.unsubscribe();
+            ({ saved } = await subscribe());
+        }
+
+        status.textContent = saved.ok
+            ? "Push notifications are on for this browser."
+            : "Push notifications aren't available.";
+    } catch {
+        status.textContent = "Push notifications were blocked.";
+    }
+}
+
+async function disablePush(status) {
+    const registration = await navigator.serviceWorker?.getRegistration("/assets/sw.js");
+    const subscription = await registration?.pushManager.getSubscription();
+
+    if (subscription) {
+        await fetch("/api/notifications/push/unsubscribe", {
+            method: "POST",
+            headers: { "Content-Type": "application/json" },
+            body: JSON.stringify({ endpoint: subscription.endpoint }),
+        });
+        await subscription.unsubscribe();
+    }
+    status.textContent = "Push notifications are off for this browser.";
+}
+
+// the server works in utc so the picked local time is sent as rfc3339
+function setUtcTime(input, target) {
+    target.value = input.value ? new Date(input.value).toISOString() : "";
+}
+
+// shows utc times from the server in the user's own time zone in time pickers
+htmx.onLoad((element) => {
+    element.querySelectorAll("input[data-utc]").forEach((input) => {
+        const date = new Date(input.dataset.utc);
+        date.setMinutes(date.getMinutes() - date.getTimezoneOffset());
+        input.value = date.toISOString().slice(0, 16);
+    });
+});
@@ -0,0 +1,15 @@
+// shows push messages from the server while no page is open
+self.addEventListener("push", (event) => {
+    const payload = event.data ? event.data.json() : { title: "Hats Chat", body: "", url: "/" };
+
+    event.waitUntil(self.registration.showNotification(payload.title, {
+        body: payload.body,
+        icon: "/assets/favicon.ico",
+        data: { url: payload.url },
+    }));
+});
+
+self.addEventListener("notificationclick", (event) => {
+    event.notification.close();
+    event.waitUntil(clients.openWindow(event.notification.data.url));
+});
@@ -0,0 +1,66 @@
+//! Stands in for a browser's push service so Web Push can be checked locally.
+//!
+//! Run it with `cargo run --example mock_push_server`, start the app with
+//! `PUSH_MOCK_ENDPOINT="http://127.0.0.1:8085/"` and subscribe with an endpoint under it.
+//! Endpoints under `/gone/` answer like an expired subscription.
+
+use axum::{body::Bytes, extract::Path, routing::post, Router};
+use http::{header, HeaderMap, StatusCode};
+
+const ADDRESS: &str = "127.0.0.1:8085";
+
+#[tokio::main]
+async fn main() {
+    let app = Router::new().route("/*endpoint", post(push));
+
+    println!("mock push service listening on http://{ADDRESS}/");
+
+    axum::Server::bind(&ADDRESS.parse().unwrap())
+        .serve(app.into_make_service())
+        .await
+        .unwrap();
+}
+
+/// Checks the request looks like what a real push service requires before accepting it.
+async fn push(
+    Path(endpoint): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> (StatusCode, &'static str) {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+    };
+
+    let problem = if header(header::CONTENT_ENCODING.as_str()) != "aes128gcm" {
+        Some("payload isn't aes128gcm encrypted")
+    } else if header("ttl").is_empty() {
+        Some("missing TTL")
+    } else if !header(header::AUTHORIZATION.as_str()).starts_with("vapid t=") {
+        Some("missing VAPID authorization")
+    } else if body.is_empty() {
+        Some("empty payload")
+    } else {
+        None
+    };
+
+    if let Some(problem) = problem {
+        println!("rejected push to /{endpoint}: {problem}");
+        return (StatusCode::BAD_REQUEST, problem);
+    }
+
+    if endpoint.starts_with("gone/") {
+        println!("push to expired /{endpoint}");
+        return (StatusCode::GONE, "subscription expired");
+    }
+
+    println!(
+        "accepted push to /{endpoint} ({} encrypted bytes, TTL {})",
+        body.len(),
+        header("ttl")
+    );
+
+    (StatusCode::CREATED, "")
+}
@@ -2,75 +2,211 @@ use std::io::Cursor;
 
 use axum::extract::{Multipart, State};
 use http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use sqlx::{Postgres, Transaction};
 
+use super::FormErrorTemplate;
 use crate::{
     data::app_state::AppState,
+    profile_pictures::{render_variants, AvatarVariant, AVATAR_SIZES},
+    storage,
     utils::{auth_layer::ExtractActivatedAuth, ToServerError},
 };
 
+pub const MAX_PROFILE_PICTURE_SIZE: usize = 8 * 1024 * 1024;
+const MAX_PROFILE_PICTURE_DIMENSION: u32 = 4096;
+const MIN_PROFILE_PICTURE_DIMENSION: u32 = 32;
+
+/// The square part of the uploaded image that becomes the profile picture.
+struct Crop {
+    x: u32,
+    y: u32,
+    size: u32,
+}
+
 pub async fn change_display_name(
     State(state): State<AppState>,
     ExtractActivatedAuth(user_id): ExtractActivatedAuth,
     mut multipart: Multipart,
-) -> Result<HeaderMap, (StatusCode, String)> {
+) -> Result<Result<HeaderMap, FormErrorTemplate>, (StatusCode, String)> {
     tracing::debug!("starting update to profile picture for user({user_id}");
 
-    let (file_type, file) = match multipart.next_field().await.server_error()? {
-        Some(field) => {
-            let name = field
-                .name()
-                .ok_or((StatusCode::BAD_REQUEST, String::from("Bad Request")))?
-                .to_owned();
-            let content_type = field
-                .content_type()
-                .ok_or((StatusCode::BAD_REQUEST, String::from("Bad Request")))?
-                .to_owned();
-            let data = field.bytes().await.server_error()?;
-
-            if name != "file" {
-                tracing::debug!("unexpected parameter name got ({name})");
-                Err((StatusCode::BAD_REQUEST, String::from("Bad Request")))?;
-            }
+    let mut file = None;
+    let mut crop_x = None;
+    let mut crop_y = None;
+    let mut crop_size = None;
 
-            if !content_type.starts_with("image/") {
-                tracing::debug!("unexpected content type got ({content_type})");
-                Err((StatusCode::BAD_REQUEST, String::from("Bad Request")))?;
-            }
+    while let Some(field) = multipart.next_field().await.server_error()? {
+        let name = field
+            .name()
+            .ok_or((StatusCode::BAD_REQUEST, String::from("Bad Request")))?
+            .to_owned();
+
+        if name == "file" {
+            let data = match field.bytes().await {
+                Err(error) if error.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+                    return Ok(Err(too_large()));
+                }
+                data => data.server_error()?,
+            };
 
-            if multipart.next_field().await.server_error()?.is_some() {
-                tracing::debug!("unexpected got second part");
-                Err((StatusCode::BAD_REQUEST, String::from("Bad Request")))?;
+            if data.len() > MAX_PROFILE_PICTURE_SIZE {
+                tracing::debug!("profile picture too large got ({} bytes)", data.len());
+                return Ok(Err(too_large()));
             }
 
-            (content_type, data)
+            file = Some(data);
+            continue;
         }
-        None => Err((StatusCode::BAD_REQUEST, String::from("Bad Request")))?,
+
+        let text = field.text().await.server_error()?;
+        // empty crop fields mean the image gets center cropped
+        let value = if text.trim().is_empty() {
+            None
+        } else {
+            Some(
+                text.trim()
+                    .parse::<u32>()
+                    .map_err(|_| (StatusCode::BAD_REQUEST, String::from("Bad Request")))?,
+            )
+        };
+
+        match name.as_str() {
+            "crop_x" => crop_x = value,
+            "crop_y" => crop_y = value,
+            "crop_size" => crop_size = value,
+            _ => {
+                tracing::debug!("unexpected parameter name got ({name})");
+                return Err((StatusCode::BAD_REQUEST, String::from("Bad Request")));
+            }
+        }
+    }
+
+    let Some(file) = file.filter(|file| !file.is_empty()) else {
+        return Ok(Err(FormErrorTemplate::new("Choose an image to upload.")));
     };
 
     tracing::debug!("decoded multipart form for new profile picture");
 
-    let file_type = image::ImageFormat::from_mime_type(file_type)
-        .ok_or((StatusCode::BAD_REQUEST, String::from("Bad Request")))?;
+    // the format comes from the file contents, not what the browser claims
+    let file_type = match image::guess_format(&file) {
+        Ok(
+            file_type @ (image::ImageFormat::Png
+            | image::ImageFormat::Jpeg
+            | image::ImageFormat::Gif
+            | image::ImageFormat::WebP
+            | image::ImageFormat::Bmp),
+        ) => file_type,
+        _ => {
+            return Ok(Err(FormErrorTemplate::new(
+                "Profile pictures must be a PNG, JPEG, GIF, WebP or BMP image.",
+            )));
+        }
+    };
 
-    let img = image::load_from_memory_with_format(&file, file_type).server_error()?;
+    // only reads the header so it is cheap enough to do here
+    let Ok((width, height)) =
+        image::io::Reader::with_format(Cursor::new(&file), file_type).into_dimensions()
+    else {
+        return Ok(Err(FormErrorTemplate::new("That image couldn't be read.")));
+    };
 
-    let mut avif_img = Vec::new();
+    if width > MAX_PROFILE_PICTURE_DIMENSION || height > MAX_PROFILE_PICTURE_DIMENSION {
+        return Ok(Err(FormErrorTemplate::new(&format!(
+            "Profile pictures can be at most {MAX_PROFILE_PICTURE_DIMENSION}x{MAX_PROFILE_PICTURE_DIMENSION} pixels."
+        ))));
+    }
 
-    img.write_to(
-        &mut Cursor::new(&mut avif_img),
-        image::ImageOutputFormat::Avif,
-    )
-    .server_error()?;
+    if width < MIN_PROFILE_PICTURE_DIMENSION || height < MIN_PROFILE_PICTURE_DIMENSION {
+        return Ok(Err(FormErrorTemplate::new(&format!(
+            "Profile pictures must be at least {MIN_PROFILE_PICTURE_DIMENSION}x{MIN_PROFILE_PICTURE_DIMENSION} pixels."
+        ))));
+    }
 
-    sqlx::query!(
-        "UPDATE users SET profile_picture = $1 WHERE id = $2",
-        avif_img,
-        user_id
-    )
-    .execute(&state.pool)
+    let crop = match (crop_x, crop_y, crop_size) {
+        (None, None, None) => {
+            let size = width.min(height);
+            Crop {
+                x: (width - size) / 2,
+                y: (height - size) / 2,
+                size,
+            }
+        }
+        (Some(x), Some(y), Some(size))
+            if size >= MIN_PROFILE_PICTURE_DIMENSION
+                && x.checked_add(size).is_some_and(|right| right <= width)
+                && y.checked_add(size).is_some_and(|bottom| bottom <= height) =>
+        {
+            Crop { x, y, size }
+        }
+        _ => {
+            return Ok(Err(FormErrorTemplate::new(&format!(
+                "The crop has to be a square of at least {MIN_PROFILE_PICTURE_DIMENSION} pixels inside the {width}x{height} image."
+            ))));
+        }
+    };
+
+    let processed = tokio::task::spawn_blocking(move || {
+        let mut reader = image::io::Reader::with_format(Cursor::new(&file), file_type);
+
+        let mut limits = image::io::Limits::default();
+        limits.max_image_width = Some(MAX_PROFILE_PICTURE_DIMENSION);
+        limits.max_image_height = Some(MAX_PROFILE_PICTURE_DIMENSION);
+        reader.limits(limits);
+
+        let Ok(img) = reader.decode() else {
+            return Ok(None);
+        };
+
+        let mut img = img.crop_imm(crop.x, crop.y, crop.size, crop.size);
+
+        // there is no point keeping more pixels than the largest size that gets shown
+        let max_size = AVATAR_SIZES[AVATAR_SIZES.len() - 1];
+        if crop.size > max_size {
+            img = img.resize_exact(max_size, max_size, image::imageops::FilterType::Lanczos3);
+        }
+
+        let mut avif_img = Vec::new();
+
+        img.write_to(
+            &mut Cursor::new(&mut avif_img),
+            image::ImageOutputFormat::Avif,
+        )?;
+
+        anyhow::Ok(Some((avif_img, render_variants(&img)?)))
+    })
     .await
+    .server_error()?
     .server_error()?;
 
+    let Some((avif_img, variants)) = processed else {
+        return Ok(Err(FormErrorTemplate::new("That image couldn't be read.")));
+    };
+
+    replace_profile_picture(&state, user_id, Some((avif_img, variants)))
+        .await
+        .server_error()?;
+
+    let mut headers = HeaderMap::default();
+
+    headers.insert(
+        HeaderName::from_static("hx-refresh"),
+        HeaderValue::from_static("true"),
+    );
+
+    Ok(Ok(headers))
+}
+
+/// Switches the user back to their identicon.
+pub async fn remove_profile_picture(
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<HeaderMap, (StatusCode, String)> {
+    replace_profile_picture(&state, user_id, None)
+        .await
+        .server_error()?;
+
+    tracing::debug!("removed profile picture of user({user_id})");
+
     let mut headers = HeaderMap::default();
 
     headers.insert(
@@ -80,3 +216,117 @@ pub async fn change_display_name(
 
     Ok(headers)
 }
+
+/// Swaps the picture and its variants and releases the blobs of the old ones.
+/// The new blobs are released again if the swap fails.
+async fn replace_profile_picture(
+    state: &AppState,
+    user_id: i32,
+    picture: Option<(Vec<u8>, Vec<AvatarVariant>)>,
+) -> anyhow::Result<()> {
+    let mut transaction = state.pool.begin().await?;
+    let mut stored_keys = vec![];
+
+    let old_keys =
+        swap_profile_picture(state, user_id, picture, &mut transaction, &mut stored_keys).await;
+
+    let old_keys = match old_keys {
+        Ok(old_keys) => transaction
+            .commit()
+            .await
+            .map(|()| old_keys)
+            .map_err(Into::into),
+        // rolled back first so the keys aren't locked anymore
+        Err(error) => {
+            drop(transaction);
+            Err(error)
+        }
+    };
+
+    let old_keys = match old_keys {
+        Ok(old_keys) => old_keys,
+        Err(error) => {
+            storage::release_all(&*state.storage, &state.pool, &stored_keys).await;
+            return Err(error);
+        }
+    };
+
+    for old_key in old_keys {
+        storage::release(&*state.storage, &state.pool, &old_key).await?;
+    }
+
+    Ok(())
+}
+
+/// Returns the keys of the old picture, every new key is added to `stored_keys` as soon as it is stored.
+async fn swap_profile_picture(
+    state: &AppState,
+    user_id: i32,
+    picture: Option<(Vec<u8>, Vec<AvatarVariant>)>,
+    transaction: &mut Transaction<'_, Postgres>,
+    stored_keys: &mut Vec<String>,
+) -> anyhow::Result<Vec<String>> {
+    let utc = time::OffsetDateTime::now_utc();
+    let timestamp = time::PrimitiveDateTime::new(utc.date(), utc.time());
+
+    let old_key = sqlx::query!(
+        "SELECT profile_picture_key FROM users WHERE id = $1 FOR UPDATE",
+        user_id
+    )
+    .fetch_one(&mut **transaction)
+    .await?
+    .profile_picture_key;
+
+    let mut old_keys = sqlx::query!(
+        "DELETE FROM profile_picture_variants WHERE user_id = $1 RETURNING storage_key",
+        user_id
+    )
+    .fetch_all(&mut **transaction)
+    .await?
+    .into_iter()
+    .map(|rec| rec.storage_key)
+    .collect::<Vec<_>>();
+    old_keys.extend(old_key);
+
+    let (key, variants) = match picture {
+        Some((avif_img, variants)) => {
+            let key = storage::store(&*state.storage, &avif_img, transaction).await?;
+            stored_keys.push(key.clone());
+            (Some(key), variants)
+        }
+        None => (None, vec![]),
+    };
+
+    sqlx::query!(
+        "UPDATE users SET profile_picture_key = $1, profile_picture_updated_at = $2 WHERE id = $3",
+        key,
+        timestamp,
+        user_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    for variant in variants {
+        let variant_key = storage::store(&*state.storage, &variant.data, transaction).await?;
+        stored_keys.push(variant_key.clone());
+
+        sqlx::query!(
+            "INSERT INTO profile_picture_variants(user_id, size, format, storage_key) VALUES ($1, $2, $3, $4)",
+            user_id,
+            variant.size,
+            variant.format.name(),
+            variant_key
+        )
+        .execute(&mut **transaction)
+        .await?;
+    }
+
+    Ok(old_keys)
+}
+
+fn too_large() -> FormErrorTemplate {
+    FormErrorTemplate::new(&format!(
+        "Profile pictures can be at most {} MB.",
+        MAX_PROFILE_PICTURE_SIZE / (1024 * 1024)
+    ))
+}
@@ -1,7 +1,10 @@
 use axum::{extract::State, Form};
 use http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
 
-use crate::{data::app_state::AppState, utils::{ToServerError, auth_layer::ExtractActivatedAuth}};
+use crate::{
+    data::app_state::AppState,
+    utils::{auth_layer::ExtractActivatedAuth, ToServerError},
+};
 
 #[derive(serde::Deserialize)]
 pub struct ChangeDisplayNameForm {
@@ -0,0 +1,37 @@
+use axum::{extract::State, Form};
+use http::{HeaderMap, StatusCode};
+
+use crate::{
+    data::app_state::AppState,
+    utils::{auth_layer::ExtractActivatedAuth, refresh, ToServerError},
+};
+
+#[derive(serde::Deserialize)]
+pub struct ChangeMessagePrivacyForm {
+    friends_only: Option<String>,
+}
+
+pub async fn change_message_privacy(
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+    Form(form): Form<ChangeMessagePrivacyForm>,
+) -> Result<HeaderMap, (StatusCode, String)> {
+    let friends_only = form.friends_only.is_some();
+
+    tracing::debug!(
+        "message privacy change for user ({}) friends only: {}",
+        user_id,
+        friends_only
+    );
+
+    sqlx::query!(
+        "UPDATE users SET friends_only_messages = $1 WHERE id = $2",
+        friends_only,
+        user_id
+    )
+    .execute(&state.pool)
+    .await
+    .server_error()?;
+
+    Ok(refresh())
+}
@@ -0,0 +1,46 @@
+use axum::{extract::State, Form};
+use http::{HeaderMap, StatusCode};
+
+use crate::{
+    data::app_state::AppState,
+    utils::{auth_layer::ExtractActivatedAuth, refresh, ToServerError},
+};
+
+pub const MAX_BIO_LENGTH: usize = 500;
+pub const MAX_STATUS_LENGTH: usize = 100;
+
+#[derive(serde::Deserialize)]
+pub struct ChangeProfileForm {
+    bio: String,
+    status: String,
+    friends_only: Option<String>,
+}
+
+pub async fn change_profile(
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+    Form(form): Form<ChangeProfileForm>,
+) -> Result<HeaderMap, (StatusCode, String)> {
+    tracing::debug!("profile change for user ({})", user_id);
+
+    let bio = form.bio.trim();
+    let status = form.status.trim();
+
+    if bio.chars().count() > MAX_BIO_LENGTH || status.chars().count() > MAX_STATUS_LENGTH {
+        tracing::debug!("bio or status too long from user ({})", user_id);
+        return Err((StatusCode::BAD_REQUEST, String::from("Bad Request")));
+    }
+
+    sqlx::query!(
+        "UPDATE users SET bio = $1, status = $2, friends_only_profile = $3 WHERE id = $4",
+        (!bio.is_empty()).then_some(bio),
+        (!status.is_empty()).then_some(status),
+        form.friends_only.is_some(),
+        user_id
+    )
+    .execute(&state.pool)
+    .await
+    .server_error()?;
+
+    Ok(refresh())
+}
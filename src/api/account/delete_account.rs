@@ -0,0 +1,62 @@
+use axum::{extract::State, Form};
+use http::{HeaderMap, StatusCode};
+
+use super::FormErrorTemplate;
+use crate::{
+    data::app_state::AppState,
+    tasks::{account_deletion::COOLING_OFF_PERIOD, now},
+    utils::{auth_layer::ExtractActivatedAuth, refresh, ToServerError},
+};
+
+#[derive(serde::Deserialize)]
+pub struct DeleteAccountForm {
+    password: String,
+}
+
+/// Schedules the account for deletion once the cooling off period is over.
+pub async fn request_deletion(
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+    Form(form): Form<DeleteAccountForm>,
+) -> Result<Result<HeaderMap, FormErrorTemplate>, (StatusCode, String)> {
+    let password_hash = sqlx::query!("SELECT password_hash FROM users WHERE id = $1", user_id)
+        .fetch_one(&state.pool)
+        .await
+        .server_error()?
+        .password_hash;
+
+    if !bcrypt::verify(form.password, &password_hash).server_error()? {
+        tracing::debug!("account deletion for user({user_id}) failed wrong password");
+        return Ok(Err(FormErrorTemplate::new("Wrong password.")));
+    }
+
+    let requested_at = now();
+
+    sqlx::query!(
+        "INSERT INTO account_deletions(user_id, requested_at, delete_after) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+        user_id,
+        requested_at,
+        requested_at + COOLING_OFF_PERIOD
+    )
+    .execute(&state.pool)
+    .await
+    .server_error()?;
+
+    tracing::debug!("user({user_id}) requested account deletion");
+
+    Ok(Ok(refresh()))
+}
+
+pub async fn cancel_deletion(
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<HeaderMap, (StatusCode, String)> {
+    sqlx::query!("DELETE FROM account_deletions WHERE user_id = $1", user_id)
+        .execute(&state.pool)
+        .await
+        .server_error()?;
+
+    tracing::debug!("user({user_id}) canceled account deletion");
+
+    Ok(refresh())
+}
@@ -0,0 +1,203 @@
+use askama::Template;
+use axum::{
+    extract::{Path, State},
+    routing::get,
+    Form, Router,
+};
+use http::{HeaderMap, StatusCode};
+use jsonwebtoken::Header;
+
+use crate::{
+    data::app_state::AppState,
+    tasks::now,
+    utils::{auth_layer::ExtractActivatedAuth, refresh, ToServerError},
+};
+
+/// How often a user can get an email about messages they haven't read.
+#[derive(Clone, Copy, PartialEq)]
+pub enum DigestFrequency {
+    Hourly,
+    Daily,
+    Weekly,
+    Never,
+}
+
+impl DigestFrequency {
+    pub const ALL: [DigestFrequency; 4] = [
+        DigestFrequency::Hourly,
+        DigestFrequency::Daily,
+        DigestFrequency::Weekly,
+        DigestFrequency::Never,
+    ];
+
+    /// Users who never picked a frequency get this one.
+    pub const DEFAULT: DigestFrequency = DigestFrequency::Daily;
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DigestFrequency::Hourly => "hourly",
+            DigestFrequency::Daily => "daily",
+            DigestFrequency::Weekly => "weekly",
+            DigestFrequency::Never => "never",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|frequency| frequency.name() == name)
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            DigestFrequency::Hourly => "At most once an hour",
+            DigestFrequency::Daily => "At most once a day",
+            DigestFrequency::Weekly => "At most once a week",
+            DigestFrequency::Never => "Never",
+        }
+    }
+
+    /// The shortest time between two digests.
+    pub fn period(&self) -> Option<time::Duration> {
+        match self {
+            DigestFrequency::Hourly => Some(time::Duration::hours(1)),
+            DigestFrequency::Daily => Some(time::Duration::days(1)),
+            DigestFrequency::Weekly => Some(time::Duration::weeks(1)),
+            DigestFrequency::Never => None,
+        }
+    }
+}
+
+pub async fn set_digest_frequency(
+    user_id: i32,
+    frequency: DigestFrequency,
+    pool: &sqlx::PgPool,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "INSERT INTO email_digests(user_id, frequency, covered_until, next_digest_at) VALUES ($1, $2, $3, $3)
+        ON CONFLICT (user_id) DO UPDATE SET frequency = EXCLUDED.frequency",
+        user_id,
+        frequency.name(),
+        now()
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(serde::Deserialize)]
+pub struct DigestFrequencyForm {
+    frequency: String,
+}
+
+pub async fn change_digest_frequency(
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+    Form(form): Form<DigestFrequencyForm>,
+) -> Result<HeaderMap, (StatusCode, String)> {
+    let frequency = DigestFrequency::from_name(&form.frequency)
+        .ok_or((StatusCode::BAD_REQUEST, String::from("Bad Request")))?;
+
+    tracing::debug!(
+        "email digest frequency change for user ({}) to {}",
+        user_id,
+        frequency.name()
+    );
+
+    set_digest_frequency(user_id, frequency, &state.pool)
+        .await
+        .server_error()?;
+
+    Ok(refresh())
+}
+
+/// Unsubscribe links have to keep working long after the email was sent.
+const UNSUBSCRIBE_TOKEN_LIFETIME_DAYS: i64 = 365;
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct UnsubscribeClaim {
+    sub: String,
+    exp: usize,
+    purpose: String,
+}
+
+const UNSUBSCRIBE_PURPOSE: &str = "email_digest_unsubscribe";
+
+pub fn unsubscribe_token(user_id: i32, jws_key: &str) -> anyhow::Result<String> {
+    let claim = UnsubscribeClaim {
+        sub: user_id.to_string(),
+        exp: (chrono::Utc::now() + chrono::Duration::days(UNSUBSCRIBE_TOKEN_LIFETIME_DAYS))
+            .timestamp() as usize,
+        purpose: String::from(UNSUBSCRIBE_PURPOSE),
+    };
+
+    Ok(jsonwebtoken::encode(
+        &Header::default(),
+        &claim,
+        &jsonwebtoken::EncodingKey::from_base64_secret(jws_key)?,
+    )?)
+}
+
+fn unsubscribe_token_user(token: &str, jws_key: &str) -> Option<i32> {
+    let claim = jsonwebtoken::decode::<UnsubscribeClaim>(
+        token,
+        &jsonwebtoken::DecodingKey::from_base64_secret(jws_key).ok()?,
+        &jsonwebtoken::Validation::default(),
+    )
+    .ok()?
+    .claims;
+
+    if claim.purpose != UNSUBSCRIBE_PURPOSE {
+        return None;
+    }
+
+    claim.sub.parse().ok()
+}
+
+/// Works without being logged in so the link in the email is all that is needed.
+/// Opening the link only asks for confirmation, so link scanners can't unsubscribe anyone.
+/// The post is also what mail clients send to unsubscribe in one click with the `List-Unsubscribe-Post` header.
+pub fn unsubscribe_routes() -> Router<AppState> {
+    Router::new().route("/digest/:token", get(confirm_unsubscribe).post(unsubscribe))
+}
+
+#[derive(Template)]
+#[template(path = "unsubscribe.html")]
+pub struct UnsubscribeTemplate {
+    token: String,
+}
+
+#[derive(Template)]
+#[template(path = "unsubscribed.html")]
+pub struct UnsubscribedTemplate;
+
+async fn confirm_unsubscribe(
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+) -> Result<UnsubscribeTemplate, (StatusCode, String)> {
+    unsubscribe_token_user(&token, &state.jws_key).ok_or((
+        StatusCode::BAD_REQUEST,
+        String::from("Invalid Unsubscribe Link"),
+    ))?;
+
+    Ok(UnsubscribeTemplate { token })
+}
+
+async fn unsubscribe(
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+) -> Result<UnsubscribedTemplate, (StatusCode, String)> {
+    let user_id = unsubscribe_token_user(&token, &state.jws_key).ok_or((
+        StatusCode::BAD_REQUEST,
+        String::from("Invalid Unsubscribe Link"),
+    ))?;
+
+    set_digest_frequency(user_id, DigestFrequency::Never, &state.pool)
+        .await
+        .server_error()?;
+
+    tracing::debug!("user({user_id}) unsubscribed from email digests");
+
+    Ok(UnsubscribedTemplate)
+}
@@ -0,0 +1,93 @@
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+};
+use http::{header, HeaderMap, HeaderValue, StatusCode};
+
+use crate::{
+    data::app_state::AppState,
+    storage,
+    tasks::{data_export::run_export, now},
+    utils::{auth_layer::ExtractActivatedAuth, refresh, ToServerError},
+};
+
+/// Starts building a new export in the background, replacing the previous one.
+pub async fn request_export(
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<HeaderMap, (StatusCode, String)> {
+    // the unique index on pending exports turns a second request into a no-op
+    let export_id = sqlx::query!(
+        "INSERT INTO data_exports(user_id, requested_at) VALUES ($1, $2) ON CONFLICT DO NOTHING RETURNING id",
+        user_id,
+        now()
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .server_error()?
+    .map(|rec| rec.id);
+
+    if let Some(export_id) = export_id {
+        let old_keys = sqlx::query!(
+            "DELETE FROM data_exports WHERE user_id = $1 AND id <> $2 RETURNING storage_key",
+            user_id,
+            export_id
+        )
+        .fetch_all(&state.pool)
+        .await
+        .server_error()?;
+
+        for key in old_keys.into_iter().filter_map(|rec| rec.storage_key) {
+            storage::release(&*state.storage, &state.pool, &key)
+                .await
+                .server_error()?;
+        }
+
+        tracing::debug!("user({user_id}) requested data export({export_id})");
+
+        tokio::spawn(run_export(state.clone(), export_id));
+    }
+
+    Ok(refresh())
+}
+
+pub async fn download_export(
+    Path(export_id): Path<i32>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let storage_key = sqlx::query!(
+        "SELECT storage_key FROM data_exports WHERE id = $1 AND user_id = $2",
+        export_id,
+        user_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .server_error()?
+    .and_then(|rec| rec.storage_key)
+    .ok_or((StatusCode::NOT_FOUND, String::from("Export Not Found")))?;
+
+    let data = state
+        .storage
+        .get(&storage_key)
+        .await
+        .server_error()?
+        .ok_or((StatusCode::NOT_FOUND, String::from("Export Not Found")))?;
+
+    let mut headers = HeaderMap::new();
+
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_static("attachment; filename=\"hats-chat-export.json\""),
+    );
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static("private, no-store"),
+    );
+
+    Ok((headers, data))
+}
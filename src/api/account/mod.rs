@@ -1,13 +1,57 @@
-use axum::{routing::put, Router};
+use askama::Template;
+use axum::{
+    extract::DefaultBodyLimit,
+    routing::{get, post, put},
+    Router,
+};
 
 use crate::data::app_state::AppState;
 
-mod change_display_name;
 mod chage_profile_picture;
+mod change_display_name;
+mod change_message_privacy;
+pub mod change_profile;
+mod delete_account;
+pub mod email_digest;
+mod export_data;
 
 pub fn account_details_uris() -> Router<AppState> {
-    Router::new().route(
-        "/display_name",
-        put(change_display_name::change_display_name),
-    ).route("/profile_picture", put(chage_profile_picture::change_display_name))
+    Router::new()
+        .route(
+            "/display_name",
+            put(change_display_name::change_display_name),
+        )
+        .route(
+            "/profile_picture",
+            put(chage_profile_picture::change_display_name)
+                .delete(chage_profile_picture::remove_profile_picture)
+                .layer(DefaultBodyLimit::max(
+                    chage_profile_picture::MAX_PROFILE_PICTURE_SIZE + 64 * 1024,
+                )),
+        )
+        .route(
+            "/message_privacy",
+            put(change_message_privacy::change_message_privacy),
+        )
+        .route("/profile", put(change_profile::change_profile))
+        .route("/delete", post(delete_account::request_deletion))
+        .route("/delete/cancel", post(delete_account::cancel_deletion))
+        .route("/export", post(export_data::request_export))
+        .route("/export/:id", get(export_data::download_export))
+        .route("/email_digest", put(email_digest::change_digest_frequency))
+}
+
+/// A message shown under a form when what was submitted can't be used.
+#[derive(Template)]
+#[template(path = "components/form_error.html")]
+pub struct FormErrorTemplate {
+    error: String,
+}
+
+impl FormErrorTemplate {
+    pub fn new(error: &str) -> Self {
+        Self {
+            error: error.to_owned(),
+        }
+    }
 }
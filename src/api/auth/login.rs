@@ -1,12 +1,16 @@
 use axum::{extract::State, Form};
 use email_address::EmailAddress;
-use http::{HeaderMap, StatusCode, HeaderName, HeaderValue};
+use http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode};
 use serde::Deserialize;
 use sqlx::PgPool;
 use tower_cookies::Cookies;
 
 use crate::{
-    api::auth::make_jwt_token, data::app_state::AppState, utils::ToServerError, LogInTemplate,
+    api::auth::make_jwt_token,
+    data::app_state::AppState,
+    notifications::{notify, NotificationKind, NotificationSubject},
+    utils::ToServerError,
+    LogInTemplate,
 };
 
 #[derive(Debug, Deserialize)]
@@ -18,6 +22,7 @@ pub struct LoginForm {
 pub async fn login(
     State(state): State<AppState>,
     cookies: Cookies,
+    request_headers: HeaderMap,
     Form(form): Form<LoginForm>,
 ) -> Result<Result<LogInTemplate, HeaderMap>, (StatusCode, String)> {
     tracing::debug!("request login for user ({}).", form.username,);
@@ -33,10 +38,25 @@ pub async fn login(
             if passwords_match {
                 tracing::debug!("password correct: id: {}.", user_id);
 
-                make_jwt_token(user_id, form.username, &cookies, state)
+                make_jwt_token(user_id, form.username, &cookies, state.clone())
                     .await
                     .server_error()?;
 
+                let user_agent = request_headers
+                    .get(header::USER_AGENT)
+                    .and_then(|user_agent| user_agent.to_str().ok());
+
+                notify(
+                    &state,
+                    user_id,
+                    NotificationKind::NewLogin,
+                    NotificationSubject {
+                        detail: user_agent,
+                        ..Default::default()
+                    },
+                )
+                .await;
+
                 tracing::debug!("created tokens: id: {}.", user_id);
 
                 let mut headers = HeaderMap::default();
@@ -73,7 +93,7 @@ async fn get_password_hash_from_username_or_email(
 ) -> anyhow::Result<Option<(i32, String)>> {
     if EmailAddress::is_valid(username) {
         Ok(sqlx::query!(
-            "SELECT id, password_hash FROM users WHERE email = $1",
+            "SELECT id, password_hash FROM users WHERE email = $1 AND deleted_at IS NULL",
             username
         )
         .fetch_optional(pool)
@@ -81,7 +101,7 @@ async fn get_password_hash_from_username_or_email(
         .map(|rec| (rec.id, rec.password_hash)))
     } else {
         Ok(sqlx::query!(
-            "SELECT id, password_hash FROM users WHERE username = $1",
+            "SELECT id, password_hash FROM users WHERE username = $1 AND deleted_at IS NULL",
             username
         )
         .fetch_optional(pool)
@@ -41,7 +41,10 @@ pub async fn logout(
         }
         None => {
             tracing::error!("schrodinger's log in for user({user_id})");
-            Err((StatusCode::INTERNAL_SERVER_ERROR, String::from("schrodinger's log in")))
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                String::from("schrodinger's log in"),
+            ))
         }
     }
 }
@@ -1,11 +1,14 @@
 use axum::{extract::State, Form};
 use email_address::EmailAddress;
-use http::{HeaderMap, StatusCode, HeaderName, HeaderValue};
+use http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
 use serde::Deserialize;
 use sqlx::PgPool;
 use tower_cookies::Cookies;
 
-use crate::{data::app_state::AppState, utils::ToServerError, SignUpTemplate, activate::send_confirmation_email};
+use crate::{
+    activate::send_confirmation_email, data::app_state::AppState, utils::ToServerError,
+    SignUpTemplate,
+};
 
 use super::make_jwt_token;
 
@@ -67,18 +70,24 @@ pub async fn signup(
 
     let password_hashed = bcrypt::hash(form.password, bcrypt::DEFAULT_COST).server_error()?;
 
+    let utc = time::OffsetDateTime::now_utc();
+    let timestamp = time::PrimitiveDateTime::new(utc.date(), utc.time());
+
     let user_id = sqlx::query!(
-        "INSERT INTO users(username, email, password_hash) VALUES ($1, $2, $3) RETURNING id;",
+        "INSERT INTO users(username, email, password_hash, created_at) VALUES ($1, $2, $3, $4) RETURNING id;",
         form.username,
         form.email,
-        password_hashed
+        password_hashed,
+        timestamp
     )
     .fetch_one(&state.pool)
     .await
     .server_error()?
     .id;
 
-    send_confirmation_email(user_id, state.clone()).await.server_error()?;
+    send_confirmation_email(user_id, state.clone())
+        .await
+        .server_error()?;
 
     make_jwt_token(user_id, form.username, &cookies, state)
         .await
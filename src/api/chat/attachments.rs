@@ -0,0 +1,298 @@
+use std::{collections::HashMap, io::Cursor};
+
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    response::IntoResponse,
+};
+use http::{header, HeaderMap, HeaderValue, StatusCode};
+use sqlx::PgPool;
+
+use crate::{
+    data::app_state::AppState,
+    utils::{auth_layer::ExtractActivatedAuth, percent_encode, ToServerError},
+};
+
+pub const MAX_ATTACHMENT_SIZE: usize = 10 * 1024 * 1024;
+pub const MAX_ATTACHMENTS: usize = 5;
+const THUMBNAIL_SIZE: u32 = 320;
+/// A small file can still claim huge dimensions, images past these are rejected before decoding.
+const MAX_IMAGE_DIMENSION: u32 = 8192;
+const MAX_IMAGE_ALLOC: u64 = 256 * 1024 * 1024;
+
+pub struct NewAttachment {
+    pub file_name: String,
+    pub content_type: String,
+    pub data: Bytes,
+    pub thumbnail: Option<Vec<u8>>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+}
+
+impl NewAttachment {
+    pub fn new(file_name: &str, data: Bytes) -> Self {
+        Self {
+            file_name: sanitize_file_name(file_name),
+            content_type: sniff_content_type(&data),
+            data,
+            thumbnail: None,
+            width: None,
+            height: None,
+        }
+    }
+
+    /// Re-encodes images to drop exif and location metadata and generates a thumbnail.
+    /// This is cpu heavy so it should be run on the blocking pool.
+    pub fn process_image(mut self) -> anyhow::Result<Self> {
+        let Some(format) = image::ImageFormat::from_mime_type(&self.content_type) else {
+            return Ok(self);
+        };
+
+        let output_format = match format {
+            image::ImageFormat::Jpeg => image::ImageOutputFormat::Jpeg(90),
+            image::ImageFormat::Png | image::ImageFormat::WebP | image::ImageFormat::Bmp => {
+                image::ImageOutputFormat::Png
+            }
+            // gifs don't carry exif and re-encoding would drop the animation
+            image::ImageFormat::Gif => image::ImageOutputFormat::Gif,
+            _ => return Ok(self),
+        };
+
+        let mut reader = image::io::Reader::with_format(Cursor::new(&self.data), format);
+
+        let mut limits = image::io::Limits::default();
+        limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+        limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+        limits.max_alloc = Some(MAX_IMAGE_ALLOC);
+        reader.limits(limits);
+
+        let img = reader.decode()?;
+
+        if format != image::ImageFormat::Gif {
+            let mut stripped = Vec::new();
+            img.write_to(&mut Cursor::new(&mut stripped), output_format.clone())?;
+
+            self.data = Bytes::from(stripped);
+            self.content_type = sniff_content_type(&self.data);
+        }
+
+        let thumbnail_format = match output_format {
+            image::ImageOutputFormat::Jpeg(_) => image::ImageOutputFormat::Jpeg(80),
+            _ => image::ImageOutputFormat::Png,
+        };
+
+        let mut thumbnail = Vec::new();
+        img.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+            .write_to(&mut Cursor::new(&mut thumbnail), thumbnail_format)?;
+
+        self.thumbnail = Some(thumbnail);
+        self.width = Some(img.width() as i32);
+        self.height = Some(img.height() as i32);
+
+        Ok(self)
+    }
+}
+
+pub struct Attachment {
+    pub id: i32,
+    pub file_name: String,
+    pub content_type: String,
+    pub size: i32,
+    pub has_thumbnail: bool,
+}
+
+impl Attachment {
+    pub fn size_text(&self) -> String {
+        let size = self.size as f64;
+
+        if size < 1024.0 {
+            format!("{} B", self.size)
+        } else if size < 1024.0 * 1024.0 {
+            format!("{:.1} KB", size / 1024.0)
+        } else {
+            format!("{:.1} MB", size / (1024.0 * 1024.0))
+        }
+    }
+}
+
+/// Attachments of every message between two users, keyed by message id.
+pub async fn conversation_attachments(
+    user_id: i32,
+    other_user_id: i32,
+    pool: &PgPool,
+) -> anyhow::Result<HashMap<i32, Vec<Attachment>>> {
+    let mut attachments: HashMap<i32, Vec<Attachment>> = HashMap::new();
+
+    let recs = sqlx::query!(
+        r#"SELECT chat_attachments.id, message_id, file_name, content_type, size, thumbnail_key IS NOT NULL AS "has_thumbnail!" FROM chat_attachments
+        JOIN chat_messages ON chat_messages.id = chat_attachments.message_id
+        WHERE (sender_id = $1 AND recipient_id = $2) OR (sender_id = $2 AND recipient_id = $1)
+        ORDER BY chat_attachments.id"#,
+        user_id,
+        other_user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for rec in recs {
+        attachments
+            .entry(rec.message_id)
+            .or_default()
+            .push(Attachment {
+                id: rec.id,
+                file_name: rec.file_name,
+                content_type: rec.content_type,
+                size: rec.size,
+                has_thumbnail: rec.has_thumbnail,
+            });
+    }
+
+    Ok(attachments)
+}
+
+pub async fn download_attachment(
+    Path(attachment_id): Path<i32>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let attachment = sqlx::query!(
+        "SELECT file_name, content_type, storage_key FROM chat_attachments
+        JOIN chat_messages ON chat_messages.id = chat_attachments.message_id
+        WHERE chat_attachments.id = $1 AND (sender_id = $2 OR recipient_id = $2)",
+        attachment_id,
+        user_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .server_error()?
+    .ok_or((StatusCode::NOT_FOUND, String::from("Attachment Not Found")))?;
+
+    tracing::debug!("user({user_id}) downloading attachment({attachment_id})");
+
+    let data = load_blob(&state, &attachment.storage_key).await?;
+
+    let mut headers = HeaderMap::new();
+
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_str(&attachment.content_type).server_error()?,
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_str(&format!(
+            "attachment; filename*=UTF-8''{}",
+            percent_encode(&attachment.file_name)
+        ))
+        .server_error()?,
+    );
+    headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+
+    Ok((headers, data))
+}
+
+/// Serves an image attachment inline so it can be shown in the lightbox.
+pub async fn view_attachment(
+    Path(attachment_id): Path<i32>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let attachment = sqlx::query!(
+        "SELECT content_type, storage_key FROM chat_attachments
+        JOIN chat_messages ON chat_messages.id = chat_attachments.message_id
+        WHERE chat_attachments.id = $1 AND thumbnail_key IS NOT NULL AND (sender_id = $2 OR recipient_id = $2)",
+        attachment_id,
+        user_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .server_error()?
+    .ok_or((StatusCode::NOT_FOUND, String::from("Image Not Found")))?;
+
+    let data = load_blob(&state, &attachment.storage_key).await?;
+
+    Ok((image_headers(&attachment.content_type)?, data))
+}
+
+pub async fn attachment_thumbnail(
+    Path(attachment_id): Path<i32>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let thumbnail_key = sqlx::query!(
+        "SELECT thumbnail_key FROM chat_attachments
+        JOIN chat_messages ON chat_messages.id = chat_attachments.message_id
+        WHERE chat_attachments.id = $1 AND (sender_id = $2 OR recipient_id = $2)",
+        attachment_id,
+        user_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .server_error()?
+    .and_then(|rec| rec.thumbnail_key)
+    .ok_or((StatusCode::NOT_FOUND, String::from("Thumbnail Not Found")))?;
+
+    let thumbnail = load_blob(&state, &thumbnail_key).await?;
+
+    let content_type = sniff_content_type(&thumbnail);
+
+    Ok((image_headers(&content_type)?, thumbnail))
+}
+
+async fn load_blob(state: &AppState, key: &str) -> Result<Vec<u8>, (StatusCode, String)> {
+    state.storage.get(key).await.server_error()?.ok_or_else(|| {
+        tracing::error!("blob ({key}) is missing from storage");
+        (StatusCode::NOT_FOUND, String::from("File Not Found"))
+    })
+}
+
+fn image_headers(content_type: &str) -> Result<HeaderMap, (StatusCode, String)> {
+    let mut headers = HeaderMap::new();
+
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_str(content_type).server_error()?,
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_static("inline"),
+    );
+    headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static("private, max-age=86400, immutable"),
+    );
+
+    Ok(headers)
+}
+
+/// The content type is taken from the file contents, never from what the browser claims.
+fn sniff_content_type(data: &[u8]) -> String {
+    match infer::get(data) {
+        Some(kind) => kind.mime_type().to_owned(),
+        None if std::str::from_utf8(data).is_ok() => String::from("text/plain"),
+        None => String::from("application/octet-stream"),
+    }
+}
+
+fn sanitize_file_name(file_name: &str) -> String {
+    let file_name = file_name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(255)
+        .collect::<String>();
+
+    if file_name.trim().is_empty() {
+        String::from("file")
+    } else {
+        file_name
+    }
+}
@@ -0,0 +1,210 @@
+use axum::{
+    extract::{Path, State},
+    Form,
+};
+use http::{HeaderMap, StatusCode};
+use sqlx::PgExecutor;
+
+use super::system::{insert_system_message, ConversationEvent};
+use crate::{
+    data::app_state::AppState,
+    tasks::now,
+    utils::{
+        auth_layer::ExtractActivatedAuth, find_user_id, refresh, relationship::can_message,
+        ToServerError,
+    },
+};
+
+/// How long messages last in a conversation with disappearing messages on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RetentionPeriod {
+    Hour,
+    Day,
+    Week,
+}
+
+impl RetentionPeriod {
+    pub const ALL: [RetentionPeriod; 3] = [
+        RetentionPeriod::Hour,
+        RetentionPeriod::Day,
+        RetentionPeriod::Week,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RetentionPeriod::Hour => "hour",
+            RetentionPeriod::Day => "day",
+            RetentionPeriod::Week => "week",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|period| period.name() == name)
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            RetentionPeriod::Hour => "1 hour",
+            RetentionPeriod::Day => "1 day",
+            RetentionPeriod::Week => "1 week",
+        }
+    }
+
+    pub fn duration(&self) -> time::Duration {
+        match self {
+            RetentionPeriod::Hour => time::Duration::hours(1),
+            RetentionPeriod::Day => time::Duration::days(1),
+            RetentionPeriod::Week => time::Duration::weeks(1),
+        }
+    }
+}
+
+/// The setting is shared so it is stored once per pair with the smaller id first.
+fn user_pair(user_id: i32, other_user_id: i32) -> (i32, i32) {
+    (user_id.min(other_user_id), user_id.max(other_user_id))
+}
+
+/// `None` while messages in the conversation are kept.
+pub async fn retention_period(
+    user_id: i32,
+    other_user_id: i32,
+    executor: impl PgExecutor<'_>,
+) -> anyhow::Result<Option<RetentionPeriod>> {
+    let (user_a, user_b) = user_pair(user_id, other_user_id);
+
+    Ok(sqlx::query!(
+        "SELECT period FROM disappearing_messages WHERE user_a = $1 AND user_b = $2",
+        user_a,
+        user_b
+    )
+    .fetch_optional(executor)
+    .await?
+    .and_then(|rec| RetentionPeriod::from_name(&rec.period)))
+}
+
+#[derive(serde::Deserialize)]
+pub struct RetentionForm {
+    /// A [`RetentionPeriod`] name or "off".
+    period: String,
+}
+
+/// Either person can change it, messages already sent keep the timer they were sent with.
+pub async fn change_retention(
+    Path(other_user_name): Path<String>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+    Form(form): Form<RetentionForm>,
+) -> Result<HeaderMap, (StatusCode, String)> {
+    let period = match form.period.as_str() {
+        "off" => None,
+        name => Some(
+            RetentionPeriod::from_name(name)
+                .ok_or((StatusCode::BAD_REQUEST, String::from("Bad Request")))?,
+        ),
+    };
+
+    let other_user_id = find_user_id(&other_user_name, &state.pool).await?;
+
+    if !can_message(user_id, other_user_id, &state.pool)
+        .await
+        .server_error()?
+    {
+        tracing::debug!("user({user_id}) is not allowed to message user({other_user_id})");
+        return Err((StatusCode::FORBIDDEN, String::from("Forbidden")));
+    }
+
+    let mut transaction = state.pool.begin().await.server_error()?;
+
+    if retention_period(user_id, other_user_id, &mut *transaction)
+        .await
+        .server_error()?
+        == period
+    {
+        return Ok(refresh());
+    }
+
+    let (user_a, user_b) = user_pair(user_id, other_user_id);
+
+    match period {
+        Some(period) => {
+            sqlx::query!(
+                "INSERT INTO disappearing_messages(user_a, user_b, period, changed_by, changed_at) VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (user_a, user_b) DO UPDATE SET period = EXCLUDED.period, changed_by = EXCLUDED.changed_by, changed_at = EXCLUDED.changed_at",
+                user_a,
+                user_b,
+                period.name(),
+                user_id,
+                now()
+            )
+            .execute(&mut *transaction)
+            .await
+            .server_error()?;
+        }
+        None => {
+            sqlx::query!(
+                "DELETE FROM disappearing_messages WHERE user_a = $1 AND user_b = $2",
+                user_a,
+                user_b
+            )
+            .execute(&mut *transaction)
+            .await
+            .server_error()?;
+        }
+    }
+
+    // both people should know their messages now disappear, or don't anymore
+    let event = match period {
+        Some(period) => ConversationEvent::DisappearingMessagesOn(period),
+        None => ConversationEvent::DisappearingMessagesOff,
+    };
+
+    insert_system_message(user_id, other_user_id, event, &mut transaction)
+        .await
+        .server_error()?;
+
+    transaction.commit().await.server_error()?;
+
+    tracing::debug!(
+        "user({user_id}) set disappearing messages with user({other_user_id}) to {}",
+        form.period
+    );
+
+    state.message_sent.send_replace((user_id, other_user_id));
+
+    Ok(refresh())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_round_trip() {
+        for period in RetentionPeriod::ALL {
+            assert_eq!(RetentionPeriod::from_name(period.name()), Some(period));
+        }
+    }
+
+    #[test]
+    fn unknown_names_are_rejected() {
+        assert_eq!(RetentionPeriod::from_name("off"), None);
+        assert_eq!(RetentionPeriod::from_name("Day"), None);
+        assert_eq!(RetentionPeriod::from_name(""), None);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(
+            RetentionPeriod::Hour.duration(),
+            time::Duration::minutes(60)
+        );
+        assert_eq!(RetentionPeriod::Day.duration(), time::Duration::hours(24));
+        assert_eq!(RetentionPeriod::Week.duration(), time::Duration::days(7));
+    }
+
+    #[test]
+    fn pairs_are_ordered() {
+        assert_eq!(user_pair(7, 3), (3, 7));
+        assert_eq!(user_pair(3, 7), (3, 7));
+    }
+}
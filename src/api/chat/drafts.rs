@@ -0,0 +1,105 @@
+use axum::{
+    extract::{Path, State},
+    Form,
+};
+use http::StatusCode;
+use sqlx::PgExecutor;
+
+use crate::{
+    data::app_state::AppState,
+    tasks::now,
+    utils::{auth_layer::ExtractActivatedAuth, find_user_id, ToServerError},
+};
+
+/// What the user had typed to the other person but not sent.
+#[derive(Default)]
+pub struct Draft {
+    /// Empty when there is nothing.
+    pub msg: String,
+    /// The last message sent from the message input, saves typed before it are stale.
+    pub last_sent_id: i32,
+}
+
+pub async fn draft(
+    user_id: i32,
+    other_user_id: i32,
+    executor: impl PgExecutor<'_>,
+) -> anyhow::Result<Draft> {
+    Ok(sqlx::query!(
+        "SELECT msg, last_sent_id FROM message_drafts WHERE user_id = $1 AND other_user_id = $2",
+        user_id,
+        other_user_id
+    )
+    .fetch_optional(executor)
+    .await?
+    .map(|rec| Draft {
+        msg: rec.msg,
+        last_sent_id: rec.last_sent_id,
+    })
+    .unwrap_or_default())
+}
+
+/// Called once the draft went out, `message_id` is the message it became when it was sent right away.
+pub async fn clear_draft(
+    user_id: i32,
+    other_user_id: i32,
+    message_id: Option<i32>,
+    executor: impl PgExecutor<'_>,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "INSERT INTO message_drafts(user_id, other_user_id, msg, updated_at, last_sent_id) VALUES ($1, $2, '', $3, COALESCE($4, 0))
+        ON CONFLICT (user_id, other_user_id) DO UPDATE SET msg = '', updated_at = EXCLUDED.updated_at,
+            last_sent_id = COALESCE($4, message_drafts.last_sent_id)",
+        user_id,
+        other_user_id,
+        now(),
+        message_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(serde::Deserialize)]
+pub struct DraftForm {
+    message: String,
+    /// The last message sent from the input when it was rendered.
+    after: i32,
+}
+
+/// The chat input saves here a moment after the user stops typing.
+/// A save can arrive after the text was already sent, those are turned away so it doesn't come back as a draft.
+pub async fn save_draft(
+    Path(other_user_name): Path<String>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+    Form(form): Form<DraftForm>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let other_user_id = find_user_id(&other_user_name, &state.pool).await?;
+
+    // one statement so a message sent in between can't be overwritten by its own stale draft
+    let saved = sqlx::query!(
+        "INSERT INTO message_drafts(user_id, other_user_id, msg, updated_at, last_sent_id) VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (user_id, other_user_id) DO UPDATE SET msg = EXCLUDED.msg, updated_at = EXCLUDED.updated_at
+        WHERE message_drafts.last_sent_id <= EXCLUDED.last_sent_id",
+        user_id,
+        other_user_id,
+        form.message,
+        now(),
+        form.after
+    )
+    .execute(&state.pool)
+    .await
+    .server_error()?
+    .rows_affected();
+
+    if saved == 0 {
+        tracing::debug!(
+            "user({user_id}) saved a draft to user({other_user_id}) that was already sent"
+        );
+        return Err((StatusCode::CONFLICT, String::from("Draft Already Sent")));
+    }
+
+    Ok(StatusCode::OK)
+}
@@ -0,0 +1,294 @@
+use askama::Template;
+use axum::{
+    body::{Bytes, StreamBody},
+    extract::{Path, Query, State},
+    response::IntoResponse,
+};
+use futures::{Stream, TryStreamExt};
+use http::{header, HeaderMap, HeaderValue, StatusCode};
+use serde::Serialize;
+use sqlx::PgPool;
+use time::{format_description::well_known::Rfc3339, macros::format_description};
+
+use crate::{
+    data::app_state::AppState,
+    utils::{
+        auth_layer::ExtractActivatedAuth, markdown::render_markdown, percent_encode,
+        username::Username, ToServerError,
+    },
+};
+
+#[derive(serde::Deserialize)]
+pub struct ExportQuery {
+    format: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+enum ExportFormat {
+    JsonLines,
+    Html,
+    Text,
+}
+
+impl ExportFormat {
+    fn parse(format: Option<&str>) -> Option<Self> {
+        match format {
+            None | Some("jsonl") => Some(ExportFormat::JsonLines),
+            Some("html") => Some(ExportFormat::Html),
+            Some("txt") => Some(ExportFormat::Text),
+            _ => None,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::JsonLines => "jsonl",
+            ExportFormat::Html => "html",
+            ExportFormat::Text => "txt",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::JsonLines => "application/jsonl; charset=utf-8",
+            ExportFormat::Html => "text/html; charset=utf-8",
+            ExportFormat::Text => "text/plain; charset=utf-8",
+        }
+    }
+}
+
+struct ExportedMessage {
+    id: i32,
+    sender: Username,
+    own: bool,
+    sent_at: time::PrimitiveDateTime,
+    msg: String,
+    /// Sanitized like in the chat window, the html transcript shows this instead of the raw text.
+    msg_html: String,
+    /// The text follows the sender's name, like in the chat window.
+    system: bool,
+    attachments: Vec<ExportedAttachment>,
+}
+
+#[derive(Serialize)]
+struct ExportedAttachment {
+    id: i32,
+    file_name: String,
+    url: String,
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    id: i32,
+    sender: String,
+    sender_name: String,
+    sent_at: String,
+    /// "user" or "system".
+    kind: &'static str,
+    message: &'a str,
+    attachments: &'a [ExportedAttachment],
+}
+
+#[derive(Template)]
+#[template(path = "transcript/start.html")]
+struct TranscriptStartTemplate {
+    other: Username,
+    style: String,
+}
+
+#[derive(Template)]
+#[template(path = "transcript/message.html")]
+struct TranscriptMessageTemplate<'a> {
+    message: &'a ExportedMessage,
+    sent_at: String,
+}
+
+#[derive(Template)]
+#[template(path = "transcript/end.html")]
+struct TranscriptEndTemplate;
+
+/// Streams the whole conversation with another user so big histories never sit in memory.
+pub async fn export_conversation(
+    Path(other_user_name): Path<String>,
+    Query(query): Query<ExportQuery>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let format = ExportFormat::parse(query.format.as_deref())
+        .ok_or((StatusCode::BAD_REQUEST, String::from("Bad Request")))?;
+
+    let other = sqlx::query!(
+        "SELECT id, username, display_name, profile_picture_key FROM users WHERE username = $1",
+        other_user_name
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .server_error()?
+    .ok_or((StatusCode::NOT_FOUND, String::from("User Not Found")))?;
+
+    let other_user_id = other.id;
+    let other_name = Username::new(
+        other.username,
+        other.display_name,
+        other.profile_picture_key,
+    );
+
+    tracing::debug!("user({user_id}) exporting conversation with user({other_user_id})");
+
+    let start = match format {
+        ExportFormat::Html => {
+            // the transcript has to work offline so the styles are inlined
+            let style = tokio::fs::read_to_string("assets/output.css")
+                .await
+                .unwrap_or_default();
+
+            Some(
+                TranscriptStartTemplate {
+                    other: other_name.clone(),
+                    style,
+                }
+                .render()
+                .server_error()?,
+            )
+        }
+        _ => None,
+    };
+
+    let stream = transcript_stream(format, start, user_id, other_user_id, state.pool.clone());
+
+    let mut headers = HeaderMap::new();
+
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(format.content_type()),
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_str(&format!(
+            "attachment; filename*=UTF-8''chat-{}.{}",
+            percent_encode(&other_name.username()),
+            format.extension()
+        ))
+        .server_error()?,
+    );
+    headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+
+    Ok((headers, StreamBody::new(stream)))
+}
+
+fn transcript_stream(
+    format: ExportFormat,
+    start: Option<String>,
+    user_id: i32,
+    other_user_id: i32,
+    pool: PgPool,
+) -> impl Stream<Item = anyhow::Result<Bytes>> {
+    async_stream::try_stream! {
+        if let Some(start) = start {
+            yield Bytes::from(start);
+        }
+
+        let mut messages = sqlx::query!(
+            r#"SELECT chat_messages.id, sender_id, username, display_name, profile_picture_key, msg, msg_html, sent_at, kind = 'system' AS "system!",
+                ARRAY(SELECT username FROM mentions JOIN users ON users.id = mentions.user_id WHERE message_id = chat_messages.id) AS "mentioned!",
+                ARRAY(SELECT id FROM chat_attachments WHERE message_id = chat_messages.id ORDER BY id) AS "attachment_ids!",
+                ARRAY(SELECT file_name FROM chat_attachments WHERE message_id = chat_messages.id ORDER BY id) AS "attachment_names!"
+            FROM chat_messages
+            JOIN users ON users.id = chat_messages.sender_id
+            WHERE (sender_id = $1 AND recipient_id = $2) OR (sender_id = $2 AND recipient_id = $1)
+            ORDER BY sent_at, chat_messages.id"#,
+            user_id,
+            other_user_id
+        )
+        .fetch(&pool);
+
+        while let Some(rec) = messages.try_next().await? {
+            // not cached here, the chat window does that the next time it's opened
+            let msg_html = match rec.msg_html {
+                Some(msg_html) => msg_html,
+                None if rec.system => String::new(),
+                None => render_markdown(&rec.msg, &rec.mentioned),
+            };
+
+            let message = ExportedMessage {
+                id: rec.id,
+                sender: Username::new(rec.username, rec.display_name, rec.profile_picture_key),
+                own: rec.sender_id == user_id,
+                sent_at: rec.sent_at,
+                msg: rec.msg,
+                msg_html,
+                system: rec.system,
+                attachments: rec
+                    .attachment_ids
+                    .into_iter()
+                    .zip(rec.attachment_names)
+                    .map(|(id, file_name)| ExportedAttachment {
+                        id,
+                        file_name,
+                        url: format!("/api/chat/attachment/{id}"),
+                    })
+                    .collect(),
+            };
+
+            yield Bytes::from(render_message(format, &message)?);
+        }
+
+        if let ExportFormat::Html = format {
+            yield Bytes::from(TranscriptEndTemplate.render()?);
+        }
+    }
+}
+
+fn render_message(format: ExportFormat, message: &ExportedMessage) -> anyhow::Result<String> {
+    match format {
+        ExportFormat::JsonLines => {
+            let mut line = serde_json::to_string(&JsonLine {
+                id: message.id,
+                sender: message.sender.username(),
+                sender_name: message.sender.display_name(),
+                sent_at: message.sent_at.assume_utc().format(&Rfc3339)?,
+                kind: if message.system { "system" } else { "user" },
+                message: &message.msg,
+                attachments: &message.attachments,
+            })?;
+            line.push('\n');
+
+            Ok(line)
+        }
+        ExportFormat::Html => Ok(TranscriptMessageTemplate {
+            message,
+            sent_at: readable_time(message.sent_at)?,
+        }
+        .render()?),
+        ExportFormat::Text => {
+            let mut text = format!(
+                "[{}] {}{} {}\n",
+                readable_time(message.sent_at)?,
+                message.sender.display_name(),
+                if message.system { "" } else { ":" },
+                message.msg
+            );
+
+            for attachment in &message.attachments {
+                text.push_str(&format!(
+                    "    attachment: {} ({})\n",
+                    attachment.file_name, attachment.url
+                ));
+            }
+
+            Ok(text)
+        }
+    }
+}
+
+fn readable_time(timestamp: time::PrimitiveDateTime) -> anyhow::Result<String> {
+    Ok(format!(
+        "{} UTC",
+        timestamp.format(format_description!(
+            "[year]-[month]-[day] [hour]:[minute]:[second]"
+        ))?
+    ))
+}
@@ -2,64 +2,386 @@ use std::{collections::HashMap, time::Duration};
 
 use askama::Template;
 use axum::{
-    extract::{Path, State},
+    extract::{DefaultBodyLimit, Multipart, Path, Query, State},
     response::{sse::Event, Sse},
-    routing::{get, post},
-    Form, Router,
+    routing::{get, post, put},
+    Router,
 };
 use futures::stream::Stream;
 use http::StatusCode;
 
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
 use time::PrimitiveDateTime;
+use tokio::sync::{broadcast, watch};
 
+use self::attachments::{
+    attachment_thumbnail, conversation_attachments, download_attachment, view_attachment,
+    Attachment, NewAttachment, MAX_ATTACHMENTS, MAX_ATTACHMENT_SIZE,
+};
+use self::disappearing::{retention_period, RetentionPeriod};
+use self::drafts::{clear_draft, draft, Draft};
+use self::pinned::{pinned_messages, PinnedMessage};
+use self::scheduled::{scheduled_messages, ScheduledMessage};
 use crate::{
-    app::BaseInfo,
-    data::app_state::AppState,
-    utils::{auth_layer::ExtractActivatedAuth, username::Username, ToServerError},
+    app::{
+        friend_list::{FiendListInfo, FriendListEntries},
+        BaseInfo,
+    },
+    data::{app_state::AppState, conversation_settings::ConversationSettings},
+    notifications::{notify, NotificationKind, NotificationSubject},
+    storage,
+    tasks::now,
+    utils::{
+        auth_layer::ExtractActivatedAuth,
+        markdown::render_markdown,
+        mentions::parse_mentions,
+        relationship::{can_message, Relationship},
+        username::Username,
+        ToServerError,
+    },
 };
 
+mod attachments;
+pub mod disappearing;
+mod drafts;
+mod export;
+mod pinned;
+mod scheduled;
+pub mod settings;
+mod starred;
+mod system;
+
 pub fn chat_routes() -> Router<AppState> {
     Router::new()
-        .route("/:recipient", post(post_chat))
+        .route(
+            "/:recipient",
+            post(post_chat).layer(DefaultBodyLimit::max(
+                MAX_ATTACHMENTS * MAX_ATTACHMENT_SIZE + 64 * 1024,
+            )),
+        )
+        .route("/attachment/:id", get(download_attachment))
+        .route("/attachment/:id/view", get(view_attachment))
+        .route("/attachment/:id/thumbnail", get(attachment_thumbnail))
+        .route("/export/:recipient", get(export::export_conversation))
+        .route("/draft/:recipient", put(drafts::save_draft))
+        .route("/event", get(sse_friend_list))
         .route("/event/:recipient", get(sse_chat_messages))
-}
-
-#[derive(serde::Deserialize)]
-struct PostChatForm {
-    message: String,
+        .route("/scheduled", post(scheduled::schedule_message))
+        .route(
+            "/scheduled/:id",
+            put(scheduled::edit_scheduled_message).delete(scheduled::cancel_scheduled_message),
+        )
+        .route(
+            "/message/:id/pin",
+            put(pinned::pin_message).delete(pinned::unpin_message),
+        )
+        .route(
+            "/message/:id/star",
+            put(starred::star_message).delete(starred::unstar_message),
+        )
+        .route(
+            "/settings/:recipient/mute",
+            put(settings::mute).delete(settings::unmute),
+        )
+        .route(
+            "/settings/:recipient/archive",
+            put(settings::archive).delete(settings::unarchive),
+        )
+        .route(
+            "/settings/:recipient/disappearing",
+            put(disappearing::change_retention),
+        )
+        .route(
+            "/settings/:recipient/pin",
+            put(settings::pin).delete(settings::unpin),
+        )
 }
 
 async fn post_chat(
     Path(recipient_name): Path<String>,
     State(state): State<AppState>,
     ExtractActivatedAuth(user_id): ExtractActivatedAuth,
-    Form(form): Form<PostChatForm>,
+    mut multipart: Multipart,
 ) -> Result<(StatusCode, String), (StatusCode, String)> {
     tracing::debug!("post chat");
 
-    match sqlx::query!("SELECT id FROM users WHERE username = $1", recipient_name)
+    // checked before reading the attachments so nobody can make the server decode images
+    // for a conversation they can't post in
+    let recipient_id = sqlx::query!("SELECT id FROM users WHERE username = $1", recipient_name)
         .fetch_optional(&state.pool)
         .await
         .server_error()?
-        .map(|rec| rec.id)
+        .ok_or((StatusCode::BAD_REQUEST, String::from("Bad Request")))?
+        .id;
+
+    if !can_message(user_id, recipient_id, &state.pool)
+        .await
+        .server_error()?
     {
-        Some(recipient_id) => {
-            let utc = time::OffsetDateTime::now_utc();
-            let timestamp = time::PrimitiveDateTime::new(utc.date(), utc.time());
+        tracing::debug!("user({user_id}) is not allowed to message user({recipient_id})");
+        return Err((StatusCode::FORBIDDEN, String::from("Forbidden")));
+    }
+
+    let mut message = String::new();
+    let mut attachments = vec![];
+
+    while let Some(field) = multipart.next_field().await.server_error()? {
+        let name = field
+            .name()
+            .ok_or((StatusCode::BAD_REQUEST, String::from("Bad Request")))?
+            .to_owned();
+
+        match name.as_str() {
+            "message" => message = field.text().await.server_error()?,
+            "files" => {
+                let file_name = field.file_name().unwrap_or_default().to_owned();
+                let data = field.bytes().await.server_error()?;
 
-            tracing::debug!("receved message from user({user_id}) to user({recipient_id})");
+                // an empty file input still sends a part
+                if file_name.is_empty() && data.is_empty() {
+                    continue;
+                }
 
-            sqlx::query!("INSERT INTO chat_messages(sender_id, recipient_id, msg, sent_at) VALUES ($1, $2, $3, $4);", user_id, recipient_id, form.message, timestamp).execute(&state.pool).await.server_error()?;
+                // before decoding, so extra files don't cost any work
+                if attachments.len() >= MAX_ATTACHMENTS {
+                    tracing::debug!("too many attachments");
+                    return Err((StatusCode::BAD_REQUEST, String::from("Too Many Files")));
+                }
 
-            state
-                .message_sent
-                .send((user_id, recipient_id))
-                .server_error()?;
+                if data.len() > MAX_ATTACHMENT_SIZE {
+                    tracing::debug!("attachment too large got ({} bytes)", data.len());
+                    return Err((
+                        StatusCode::PAYLOAD_TOO_LARGE,
+                        String::from("File Too Large"),
+                    ));
+                }
 
-            Ok((StatusCode::OK, String::from("Ok")))
+                let attachment = NewAttachment::new(&file_name, data);
+                let attachment = tokio::task::spawn_blocking(move || attachment.process_image())
+                    .await
+                    .server_error()?
+                    .map_err(|error| {
+                        tracing::debug!("failed to process image attachment ({error})");
+                        (StatusCode::BAD_REQUEST, String::from("Invalid Image"))
+                    })?;
+
+                attachments.push(attachment);
+            }
+            _ => {
+                tracing::debug!("unexpected parameter name got ({name})");
+                return Err((StatusCode::BAD_REQUEST, String::from("Bad Request")));
+            }
         }
-        None => Err((StatusCode::BAD_REQUEST, String::from("Bad Request"))),
+    }
+
+    if message.trim().is_empty() && attachments.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, String::from("Bad Request")));
+    }
+
+    tracing::debug!(
+        "receved message from user({user_id}) to user({recipient_id}) with {} attachments",
+        attachments.len()
+    );
+
+    let sent = send_message(&state, user_id, recipient_id, message, attachments)
+        .await
+        .server_error()?;
+
+    announce_message(&state, &sent).await;
+
+    Ok((StatusCode::OK, String::from("Ok")))
+}
+
+/// Stores the attachments and the message in one transaction.
+/// The blobs are released again if the message doesn't make it into the conversation.
+async fn send_message(
+    state: &AppState,
+    user_id: i32,
+    recipient_id: i32,
+    message: String,
+    attachments: Vec<NewAttachment>,
+) -> anyhow::Result<SentMessage> {
+    let mut transaction = state.pool.begin().await?;
+    let mut stored_keys = vec![];
+
+    let sent = async {
+        let mut stored_attachments = Vec::with_capacity(attachments.len());
+
+        for attachment in attachments {
+            let storage_key =
+                storage::store(&*state.storage, &attachment.data, &mut transaction).await?;
+            stored_keys.push(storage_key.clone());
+
+            let thumbnail_key = match &attachment.thumbnail {
+                Some(thumbnail) => {
+                    let thumbnail_key =
+                        storage::store(&*state.storage, thumbnail, &mut transaction).await?;
+                    stored_keys.push(thumbnail_key.clone());
+                    Some(thumbnail_key)
+                }
+                None => None,
+            };
+
+            stored_attachments.push((attachment, storage_key, thumbnail_key));
+        }
+
+        let sent = insert_message(
+            OutgoingMessage {
+                sender_id: user_id,
+                recipient_id,
+                msg: message,
+                attachments: stored_attachments,
+            },
+            now(),
+            &mut transaction,
+        )
+        .await?;
+
+        clear_draft(user_id, recipient_id, Some(sent.id), &mut *transaction).await?;
+
+        anyhow::Ok(sent)
+    }
+    .await;
+
+    let sent = match sent {
+        Ok(sent) => transaction
+            .commit()
+            .await
+            .map(|()| sent)
+            .map_err(Into::into),
+        // rolled back first so the keys aren't locked anymore
+        Err(error) => {
+            drop(transaction);
+            Err(error)
+        }
+    };
+
+    if sent.is_err() {
+        storage::release_all(&*state.storage, &state.pool, &stored_keys).await;
+    }
+
+    sent
+}
+
+/// A message on its way into a conversation, its attachments are already in storage.
+pub struct OutgoingMessage {
+    pub sender_id: i32,
+    pub recipient_id: i32,
+    pub msg: String,
+    pub attachments: Vec<(NewAttachment, String, Option<String>)>,
+}
+
+pub struct SentMessage {
+    pub id: i32,
+    pub sender_id: i32,
+    pub recipient_id: i32,
+    pub mentions_recipient: bool,
+}
+
+/// Stores the message with its rendered html, attachments and mentions.
+/// Nobody is told about it until [`announce_message`] is called after the transaction commits.
+pub async fn insert_message(
+    message: OutgoingMessage,
+    sent_at: PrimitiveDateTime,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> anyhow::Result<SentMessage> {
+    // only people in the conversation can be mentioned
+    let mentioned_users = sqlx::query!(
+        "SELECT id, username FROM users WHERE username = ANY($1) AND id = $2",
+        &parse_mentions(&message.msg),
+        message.recipient_id
+    )
+    .fetch_all(&mut **transaction)
+    .await?;
+
+    let mentioned = mentioned_users
+        .iter()
+        .map(|rec| rec.username.clone())
+        .collect::<Vec<_>>();
+
+    let msg_html = render_markdown(&message.msg, &mentioned);
+
+    let expires_at = retention_period(message.sender_id, message.recipient_id, &mut **transaction)
+        .await?
+        .map(|period| sent_at + period.duration());
+
+    let message_id = sqlx::query!(
+        "INSERT INTO chat_messages(sender_id, recipient_id, msg, msg_html, sent_at, expires_at) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id;",
+        message.sender_id,
+        message.recipient_id,
+        message.msg,
+        msg_html,
+        sent_at,
+        expires_at
+    )
+    .fetch_one(&mut **transaction)
+    .await?
+    .id;
+
+    for (attachment, storage_key, thumbnail_key) in message.attachments {
+        sqlx::query!(
+            "INSERT INTO chat_attachments(message_id, file_name, content_type, size, storage_key, thumbnail_key, width, height) VALUES ($1, $2, $3, $4, $5, $6, $7, $8);",
+            message_id,
+            attachment.file_name,
+            attachment.content_type,
+            attachment.data.len() as i32,
+            storage_key,
+            thumbnail_key,
+            attachment.width,
+            attachment.height
+        )
+        .execute(&mut **transaction)
+        .await?;
+    }
+
+    for mentioned_user in &mentioned_users {
+        sqlx::query!(
+            "INSERT INTO mentions(message_id, user_id) VALUES ($1, $2);",
+            message_id,
+            mentioned_user.id
+        )
+        .execute(&mut **transaction)
+        .await?;
+    }
+
+    Ok(SentMessage {
+        id: message_id,
+        sender_id: message.sender_id,
+        recipient_id: message.recipient_id,
+        mentions_recipient: mentioned_users
+            .iter()
+            .any(|rec| rec.id == message.recipient_id),
+    })
+}
+
+/// Updates the open pages of both people and notifies the recipient.
+pub async fn announce_message(state: &AppState, message: &SentMessage) {
+    state
+        .message_sent
+        .send_replace((message.sender_id, message.recipient_id));
+
+    let subject = NotificationSubject {
+        actor_id: Some(message.sender_id),
+        message_id: Some(message.id),
+        ..Default::default()
+    };
+
+    if message.mentions_recipient {
+        notify(
+            state,
+            message.recipient_id,
+            NotificationKind::Mention,
+            subject,
+        )
+        .await;
+    } else if !state.online_users.is_online(message.recipient_id) {
+        notify(
+            state,
+            message.recipient_id,
+            NotificationKind::Message,
+            subject,
+        )
+        .await;
     }
 }
 
@@ -77,23 +399,23 @@ async fn sse_chat_messages(
         .id;
 
     let mut listener = state.message_sent.subscribe();
+    let mut deleted = state.messages_deleted.subscribe();
 
     let stream = async_stream::stream! {
         loop {
-            listener.changed().await?;
-            let payload = *listener.borrow();
+            let payload = next_conversation_change(&mut listener, &mut deleted, (user_id, other_user_id)).await?;
 
             tracing::debug!("processing new message. payload({payload:?})");
 
             if payload == (user_id, other_user_id) || payload == (other_user_id, user_id) {
                 tracing::debug!("message valid");
 
-                let chat_window = ChatWindow {
+                let chat_update = ChatUpdate {
                     base_info: BaseInfo::new(user_id, &state.pool).await?,
-                    chat_window_info: Some(ChatWindowInfo::new(user_id, other_user_id, &state.pool).await?),
+                    chat_window_info: ChatWindowInfo::new(user_id, other_user_id, &state.pool).await?,
                 };
 
-                let html = chat_window.render()?.replace(&['\n', '\r'], "");
+                let html = chat_update.render()?.replace(&['\n', '\r'], "");
 
                 tracing::debug!("SSE responce sent to user({user_id})");
 
@@ -109,20 +431,120 @@ async fn sse_chat_messages(
     ))
 }
 
+/// Waits for a message to be sent or deleted and returns the conversation it was in.
+/// When deletions were missed `missed` is returned so the caller renders again anyway.
+async fn next_conversation_change(
+    listener: &mut watch::Receiver<(i32, i32)>,
+    deleted: &mut broadcast::Receiver<(i32, i32)>,
+    missed: (i32, i32),
+) -> anyhow::Result<(i32, i32)> {
+    tokio::select! {
+        changed = listener.changed() => {
+            changed?;
+            Ok(*listener.borrow())
+        }
+        conversation = deleted.recv() => match conversation {
+            Ok(conversation) => Ok(conversation),
+            Err(broadcast::error::RecvError::Lagged(_)) => Ok(missed),
+            Err(error) => Err(error.into()),
+        },
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct FriendListEventQuery {
+    selected: Option<String>,
+}
+
+async fn sse_friend_list(
+    Query(query): Query<FriendListEventQuery>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<Sse<impl Stream<Item = Result<Event, anyhow::Error>>>, (StatusCode, String)> {
+    tracing::debug!("sse friend list start for user({user_id})");
+
+    let mut listener = state.message_sent.subscribe();
+    let mut deleted = state.messages_deleted.subscribe();
+
+    let stream = async_stream::stream! {
+        loop {
+            let (sender_id, recipient_id) = next_conversation_change(&mut listener, &mut deleted, (user_id, user_id)).await?;
+
+            if sender_id == user_id || recipient_id == user_id {
+                let friend_list = FriendListEntries {
+                    friend_list_info: FiendListInfo::new(user_id, query.selected.clone(), &state.pool).await?,
+                };
+
+                let html = friend_list.render()?.replace(&['\n', '\r'], "");
+
+                tracing::debug!("SSE friend list sent to user({user_id})");
+
+                yield Ok(Event::default().event("friend_list").data(html));
+            }
+        }
+    };
+
+    Ok(Sse::new(stream).keep_alive(
+        axum::response::sse::KeepAlive::new()
+            .interval(Duration::from_secs(1))
+            .text("keep-alive-text"),
+    ))
+}
+
+/// What an open chat window gets when something in the conversation changed.
 #[derive(Template)]
-#[template(path = "components/chat_window.html")]
-pub struct ChatWindow {
+#[template(path = "components/chat_update.html")]
+pub struct ChatUpdate {
     pub base_info: BaseInfo,
-    pub chat_window_info: Option<ChatWindowInfo>,
+    pub chat_window_info: ChatWindowInfo,
+}
+
+pub struct ChatMessage {
+    pub id: i32,
+    pub sender_id: i32,
+    pub msg: String,
+    pub msg_html: String,
+    /// Emitted by the server, see [`system::ConversationEvent`].
+    pub system: bool,
+    pub mentions_you: bool,
+    pub pinned: bool,
+    pub starred: bool,
+    pub sent_at: PrimitiveDateTime,
+    pub attachments: Vec<Attachment>,
 }
 
 pub struct ChatWindowInfo {
-    pub messages: Vec<(i32, String, PrimitiveDateTime)>,
+    pub messages: Vec<ChatMessage>,
     pub usernames: HashMap<i32, Username>,
     pub recipient_name: String,
+    pub recipient: Username,
+    pub relationship: Relationship,
+    pub can_message: bool,
+    pub settings: ConversationSettings,
+    pub pinned_messages: Vec<PinnedMessage>,
+    pub scheduled_messages: Vec<ScheduledMessage>,
+    pub retention: Option<RetentionPeriod>,
+    /// Put back into the message input.
+    pub draft: Draft,
+    /// Shown for a sender that isn't in `usernames` instead of failing the whole page.
+    pub unknown_sender: Username,
 }
 
 impl ChatWindowInfo {
+    pub fn sender(&self, sender_id: &i32) -> &Username {
+        self.usernames
+            .get(sender_id)
+            .unwrap_or(&self.unknown_sender)
+    }
+
+    pub fn retention_periods(&self) -> [RetentionPeriod; 3] {
+        RetentionPeriod::ALL
+    }
+
+    pub fn is_retention(&self, period: &RetentionPeriod) -> bool {
+        self.retention == Some(*period)
+    }
+
     pub async fn new(user_id: i32, other_user_id: i32, pool: &PgPool) -> anyhow::Result<Self> {
         tracing::debug!("retriving messages between user({user_id}) and user({other_user_id})");
 
@@ -133,31 +555,147 @@ impl ChatWindowInfo {
                 .username;
 
         let usernames = sqlx::query!(
-            "SELECT id, display_name, username FROM users WHERE id = $1 OR id = $2",
+            "SELECT id, display_name, username, profile_picture_key FROM users WHERE id = $1 OR id = $2",
             user_id,
             other_user_id
         )
         .fetch_all(pool)
         .await?
         .into_iter()
-        .map(|rec| (rec.id, Username::new(rec.username, rec.display_name)))
+        .map(|rec| {
+            (
+                rec.id,
+                Username::new(rec.username, rec.display_name, rec.profile_picture_key),
+            )
+        })
         .collect::<HashMap<_, _>>();
 
-        let mut messages: Vec<_> = sqlx::query!(
-            "SELECT sender_id, msg, sent_at FROM chat_messages WHERE (sender_id = $1 AND recipient_id = $2) OR (sender_id = $2 AND recipient_id = $1)",
+        let mut attachments = conversation_attachments(user_id, other_user_id, pool).await?;
+
+        let mut messages = vec![];
+        let mut rendered_ids = vec![];
+        let mut rendered_html = vec![];
+
+        for rec in sqlx::query!(
+            r#"SELECT id, sender_id, msg, msg_html, sent_at, kind = 'system' AS "system!",
+                EXISTS(SELECT 1 FROM mentions WHERE message_id = chat_messages.id AND user_id = $1) AS "mentions_you!",
+                EXISTS(SELECT 1 FROM pinned_messages WHERE message_id = chat_messages.id) AS "pinned!",
+                EXISTS(SELECT 1 FROM starred_messages WHERE message_id = chat_messages.id AND user_id = $1) AS "starred!",
+                ARRAY(SELECT username FROM mentions JOIN users ON users.id = mentions.user_id WHERE message_id = chat_messages.id) AS "mentioned!"
+            FROM chat_messages
+            WHERE (sender_id = $1 AND recipient_id = $2) OR (sender_id = $2 AND recipient_id = $1)
+            ORDER BY sent_at, id"#,
             user_id,
             other_user_id
         )
         .fetch_all(pool)
-        .await
-        ?.into_iter().map(|r| (r.sender_id, r.msg, r.sent_at)).collect();
+        .await?
+        {
+            // messages sent before markdown rendering, or that mentioned a deleted user, get rendered once and cached
+            let msg_html = match rec.msg_html {
+                Some(msg_html) => msg_html,
+                // system messages are shown as plain text
+                None if rec.system => String::new(),
+                None => {
+                    let msg_html = render_markdown(&rec.msg, &rec.mentioned);
+                    rendered_ids.push(rec.id);
+                    rendered_html.push(msg_html.clone());
+                    msg_html
+                }
+            };
+
+            messages.push(ChatMessage {
+                id: rec.id,
+                sender_id: rec.sender_id,
+                msg: rec.msg,
+                msg_html,
+                system: rec.system,
+                mentions_you: rec.mentions_you,
+                pinned: rec.pinned,
+                starred: rec.starred,
+                sent_at: rec.sent_at,
+                attachments: attachments.remove(&rec.id).unwrap_or_default(),
+            });
+        }
+
+        if !rendered_ids.is_empty() {
+            sqlx::query!(
+                "UPDATE chat_messages SET msg_html = rendered.msg_html
+                FROM UNNEST($1::INT[], $2::TEXT[]) AS rendered(id, msg_html)
+                WHERE chat_messages.id = rendered.id",
+                &rendered_ids,
+                &rendered_html
+            )
+            .execute(pool)
+            .await?;
+        }
+
+        // the user has now read the whole conversation
+        sqlx::query!(
+            "INSERT INTO conversation_reads(user_id, other_user_id, last_read_at) VALUES ($1, $2, $3)
+            ON CONFLICT (user_id, other_user_id) DO UPDATE SET last_read_at = EXCLUDED.last_read_at",
+            user_id,
+            other_user_id,
+            now()
+        )
+        .execute(pool)
+        .await?;
+        sqlx::query!(
+            "UPDATE mentions SET seen = true WHERE user_id = $1 AND seen = false AND message_id IN (SELECT id FROM chat_messages WHERE sender_id = $2 AND recipient_id = $1)",
+            user_id,
+            other_user_id
+        )
+        .execute(pool)
+        .await?;
+
+        let recipient = usernames
+            .get(&other_user_id)
+            .cloned()
+            .ok_or(anyhow::anyhow!("recipient user({other_user_id}) not found"))?;
 
-        messages.sort_by(|(_, _, a), (_, _, b)| a.cmp(b));
+        let relationship = Relationship::between(user_id, other_user_id, pool).await?;
+        let can_message = can_message(user_id, other_user_id, pool).await?;
+        let settings = ConversationSettings::new(user_id, other_user_id, pool).await?;
+        let pinned_messages = pinned_messages(user_id, other_user_id, pool).await?;
+        let scheduled_messages = scheduled_messages(user_id, other_user_id, pool).await?;
+        let retention = retention_period(user_id, other_user_id, pool).await?;
+        let draft = draft(user_id, other_user_id, pool).await?;
 
         Ok(Self {
             messages,
             usernames,
             recipient_name,
+            recipient,
+            relationship,
+            can_message,
+            settings,
+            pinned_messages,
+            scheduled_messages,
+            retention,
+            draft,
+            unknown_sender: Username::new(
+                String::from("unknown"),
+                Some(String::from("Unknown user")),
+                None,
+            ),
         })
     }
 }
+
+/// The sender and recipient of a message the user is part of, other messages and system messages are not found.
+async fn message_conversation(
+    message_id: i32,
+    user_id: i32,
+    pool: &PgPool,
+) -> Result<(i32, i32), (StatusCode, String)> {
+    sqlx::query!(
+        "SELECT sender_id, recipient_id FROM chat_messages WHERE id = $1 AND kind = 'user' AND (sender_id = $2 OR recipient_id = $2)",
+        message_id,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .server_error()?
+    .map(|rec| (rec.sender_id, rec.recipient_id))
+    .ok_or((StatusCode::NOT_FOUND, String::from("Message Not Found")))
+}
@@ -0,0 +1,93 @@
+use axum::extract::{Path, State};
+use http::StatusCode;
+use sqlx::PgPool;
+
+use super::message_conversation;
+use crate::{
+    data::app_state::AppState,
+    tasks::now,
+    utils::{auth_layer::ExtractActivatedAuth, username::Username, ToServerError},
+};
+
+/// Pins are shared, both people in the conversation see the same ones.
+pub struct PinnedMessage {
+    pub id: i32,
+    pub sender: Username,
+    pub preview: String,
+}
+
+pub async fn pinned_messages(
+    user_id: i32,
+    other_user_id: i32,
+    pool: &PgPool,
+) -> anyhow::Result<Vec<PinnedMessage>> {
+    Ok(sqlx::query!(
+        r#"SELECT chat_messages.id, LEFT(msg, $3) AS "preview!", users.username, users.display_name, users.profile_picture_key
+        FROM pinned_messages
+        JOIN chat_messages ON chat_messages.id = pinned_messages.message_id
+        JOIN users ON users.id = chat_messages.sender_id
+        WHERE (sender_id = $1 AND recipient_id = $2) OR (sender_id = $2 AND recipient_id = $1)
+        ORDER BY pinned_at DESC"#,
+        user_id,
+        other_user_id,
+        PREVIEW_LENGTH
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|rec| PinnedMessage {
+        id: rec.id,
+        sender: Username::new(rec.username, rec.display_name, rec.profile_picture_key),
+        preview: rec.preview,
+    })
+    .collect())
+}
+
+const PREVIEW_LENGTH: i32 = 60;
+
+pub async fn pin_message(
+    Path(message_id): Path<i32>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let conversation = message_conversation(message_id, user_id, &state.pool).await?;
+
+    sqlx::query!(
+        "INSERT INTO pinned_messages(message_id, pinned_by, pinned_at) VALUES ($1, $2, $3) ON CONFLICT (message_id) DO NOTHING",
+        message_id,
+        user_id,
+        now()
+    )
+    .execute(&state.pool)
+    .await
+    .server_error()?;
+
+    tracing::debug!("user({user_id}) pinned message({message_id})");
+
+    // both open chat windows show the new pin
+    state.message_sent.send_replace(conversation);
+
+    Ok(StatusCode::OK)
+}
+
+pub async fn unpin_message(
+    Path(message_id): Path<i32>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let conversation = message_conversation(message_id, user_id, &state.pool).await?;
+
+    sqlx::query!(
+        "DELETE FROM pinned_messages WHERE message_id = $1",
+        message_id
+    )
+    .execute(&state.pool)
+    .await
+    .server_error()?;
+
+    tracing::debug!("user({user_id}) unpinned message({message_id})");
+
+    state.message_sent.send_replace(conversation);
+
+    Ok(StatusCode::OK)
+}
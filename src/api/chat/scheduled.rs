@@ -0,0 +1,222 @@
+use axum::{
+    extract::{Path, State},
+    Form,
+};
+use http::{HeaderMap, StatusCode};
+use sqlx::PgPool;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime, PrimitiveDateTime};
+
+use super::drafts::clear_draft;
+use crate::{
+    api::account::FormErrorTemplate,
+    data::app_state::AppState,
+    tasks::now,
+    utils::{
+        auth_layer::ExtractActivatedAuth, refresh, relationship::can_message,
+        relative_time::full_date, ToServerError,
+    },
+};
+
+/// Pending messages one user can have waiting across all their conversations.
+const MAX_SCHEDULED_MESSAGES: i64 = 100;
+
+/// How far ahead a message can be scheduled.
+const MAX_SCHEDULE_AHEAD: time::Duration = time::Duration::days(365);
+
+/// A message the user wrote that the scheduler hasn't sent yet, only the sender sees these.
+pub struct ScheduledMessage {
+    pub id: i32,
+    pub msg: String,
+    pub send_at: PrimitiveDateTime,
+    /// The recipient couldn't be messaged anymore when it was due.
+    pub failed: bool,
+}
+
+impl ScheduledMessage {
+    pub fn send_at_text(&self) -> String {
+        format!(
+            "{} {:02}:{:02} UTC",
+            full_date(self.send_at),
+            self.send_at.hour(),
+            self.send_at.minute()
+        )
+    }
+
+    /// For the browser to turn into its own time zone.
+    pub fn send_at_rfc3339(&self) -> String {
+        self.send_at
+            .assume_utc()
+            .format(&Rfc3339)
+            .unwrap_or_default()
+    }
+}
+
+pub async fn scheduled_messages(
+    user_id: i32,
+    other_user_id: i32,
+    pool: &PgPool,
+) -> anyhow::Result<Vec<ScheduledMessage>> {
+    Ok(sqlx::query_as!(
+        ScheduledMessage,
+        "SELECT id, msg, send_at, failed FROM scheduled_messages WHERE sender_id = $1 AND recipient_id = $2 ORDER BY send_at, id",
+        user_id,
+        other_user_id
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+#[derive(serde::Deserialize)]
+pub struct ScheduleForm {
+    recipient: String,
+    message: String,
+    /// Rfc3339 in utc, the browser converts what the user picked.
+    send_at: String,
+}
+
+pub async fn schedule_message(
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+    Form(form): Form<ScheduleForm>,
+) -> Result<Result<HeaderMap, FormErrorTemplate>, (StatusCode, String)> {
+    let recipient_id = sqlx::query!("SELECT id FROM users WHERE username = $1", form.recipient)
+        .fetch_optional(&state.pool)
+        .await
+        .server_error()?
+        .ok_or((StatusCode::BAD_REQUEST, String::from("Bad Request")))?
+        .id;
+
+    if !can_message(user_id, recipient_id, &state.pool)
+        .await
+        .server_error()?
+    {
+        tracing::debug!("user({user_id}) is not allowed to message user({recipient_id})");
+        return Err((StatusCode::FORBIDDEN, String::from("Forbidden")));
+    }
+
+    let send_at = match validate(&form.message, &form.send_at) {
+        Ok(send_at) => send_at,
+        Err(error) => return Ok(Err(error)),
+    };
+
+    let pending = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM scheduled_messages WHERE sender_id = $1"#,
+        user_id
+    )
+    .fetch_one(&state.pool)
+    .await
+    .server_error()?
+    .count;
+
+    if pending >= MAX_SCHEDULED_MESSAGES {
+        return Ok(Err(FormErrorTemplate::new(&format!(
+            "You can't have more than {MAX_SCHEDULED_MESSAGES} scheduled messages."
+        ))));
+    }
+
+    let id = sqlx::query!(
+        "INSERT INTO scheduled_messages(sender_id, recipient_id, msg, send_at, created_at) VALUES ($1, $2, $3, $4, $5) RETURNING id",
+        user_id,
+        recipient_id,
+        form.message,
+        send_at,
+        now()
+    )
+    .fetch_one(&state.pool)
+    .await
+    .server_error()?
+    .id;
+
+    // the draft is what got scheduled
+    clear_draft(user_id, recipient_id, None, &state.pool)
+        .await
+        .server_error()?;
+
+    tracing::debug!("user({user_id}) scheduled message({id}) to user({recipient_id}) at {send_at}");
+
+    Ok(Ok(refresh()))
+}
+
+#[derive(serde::Deserialize)]
+pub struct EditScheduledForm {
+    message: String,
+    send_at: String,
+}
+
+pub async fn edit_scheduled_message(
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+    Form(form): Form<EditScheduledForm>,
+) -> Result<Result<HeaderMap, FormErrorTemplate>, (StatusCode, String)> {
+    let send_at = match validate(&form.message, &form.send_at) {
+        Ok(send_at) => send_at,
+        Err(error) => return Ok(Err(error)),
+    };
+
+    // a failed message gets another try at the new time
+    sqlx::query!(
+        "UPDATE scheduled_messages SET msg = $1, send_at = $2, failed = false WHERE id = $3 AND sender_id = $4 RETURNING id",
+        form.message,
+        send_at,
+        id,
+        user_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .server_error()?
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        String::from("Scheduled Message Not Found"),
+    ))?;
+
+    tracing::debug!("user({user_id}) edited scheduled message({id})");
+
+    Ok(Ok(refresh()))
+}
+
+pub async fn cancel_scheduled_message(
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<HeaderMap, (StatusCode, String)> {
+    sqlx::query!(
+        "DELETE FROM scheduled_messages WHERE id = $1 AND sender_id = $2 RETURNING id",
+        id,
+        user_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .server_error()?
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        String::from("Scheduled Message Not Found"),
+    ))?;
+
+    tracing::debug!("user({user_id}) canceled scheduled message({id})");
+
+    Ok(refresh())
+}
+
+fn validate(message: &str, send_at: &str) -> Result<PrimitiveDateTime, FormErrorTemplate> {
+    if message.trim().is_empty() {
+        return Err(FormErrorTemplate::new("Write a message to schedule."));
+    }
+
+    let send_at = OffsetDateTime::parse(send_at, &Rfc3339)
+        .map_err(|_| FormErrorTemplate::new("Pick when to send the message."))?
+        .to_offset(time::UtcOffset::UTC);
+    let send_at = PrimitiveDateTime::new(send_at.date(), send_at.time());
+
+    if send_at <= now() {
+        return Err(FormErrorTemplate::new("Pick a time in the future."));
+    }
+
+    if send_at > now() + MAX_SCHEDULE_AHEAD {
+        return Err(FormErrorTemplate::new(
+            "Messages can't be scheduled more than a year ahead.",
+        ));
+    }
+
+    Ok(send_at)
+}
@@ -0,0 +1,151 @@
+use axum::{
+    extract::{Path, State},
+    Form,
+};
+use http::{HeaderMap, StatusCode};
+use sqlx::PgPool;
+
+use crate::{
+    data::{app_state::AppState, conversation_settings::MuteDuration},
+    tasks::now,
+    utils::{auth_layer::ExtractActivatedAuth, find_user_id, refresh, ToServerError},
+};
+
+#[derive(serde::Deserialize)]
+pub struct MuteForm {
+    duration: String,
+}
+
+pub async fn mute(
+    Path(other_user_name): Path<String>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+    Form(form): Form<MuteForm>,
+) -> Result<HeaderMap, (StatusCode, String)> {
+    let duration = MuteDuration::from_name(&form.duration)
+        .ok_or((StatusCode::BAD_REQUEST, String::from("Bad Request")))?;
+    let other_user_id = find_user_id(&other_user_name, &state.pool).await?;
+
+    sqlx::query!(
+        "INSERT INTO conversation_settings(user_id, other_user_id, muted, muted_until) VALUES ($1, $2, true, $3)
+        ON CONFLICT (user_id, other_user_id) DO UPDATE SET muted = true, muted_until = EXCLUDED.muted_until",
+        user_id,
+        other_user_id,
+        duration.duration().map(|duration| now() + duration)
+    )
+    .execute(&state.pool)
+    .await
+    .server_error()?;
+
+    tracing::debug!(
+        "user({user_id}) muted user({other_user_id}) {}",
+        duration.name()
+    );
+
+    Ok(refresh())
+}
+
+pub async fn unmute(
+    Path(other_user_name): Path<String>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<HeaderMap, (StatusCode, String)> {
+    let other_user_id = find_user_id(&other_user_name, &state.pool).await?;
+
+    sqlx::query!(
+        "UPDATE conversation_settings SET muted = false, muted_until = NULL WHERE user_id = $1 AND other_user_id = $2",
+        user_id,
+        other_user_id
+    )
+    .execute(&state.pool)
+    .await
+    .server_error()?;
+
+    tracing::debug!("user({user_id}) unmuted user({other_user_id})");
+
+    Ok(refresh())
+}
+
+pub async fn archive(
+    Path(other_user_name): Path<String>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<HeaderMap, (StatusCode, String)> {
+    let other_user_id = find_user_id(&other_user_name, &state.pool).await?;
+
+    sqlx::query!(
+        "INSERT INTO conversation_settings(user_id, other_user_id, archived_at) VALUES ($1, $2, $3)
+        ON CONFLICT (user_id, other_user_id) DO UPDATE SET archived_at = EXCLUDED.archived_at",
+        user_id,
+        other_user_id,
+        now()
+    )
+    .execute(&state.pool)
+    .await
+    .server_error()?;
+
+    tracing::debug!("user({user_id}) archived conversation with user({other_user_id})");
+
+    Ok(refresh())
+}
+
+pub async fn unarchive(
+    Path(other_user_name): Path<String>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<HeaderMap, (StatusCode, String)> {
+    let other_user_id = find_user_id(&other_user_name, &state.pool).await?;
+
+    sqlx::query!(
+        "UPDATE conversation_settings SET archived_at = NULL WHERE user_id = $1 AND other_user_id = $2",
+        user_id,
+        other_user_id
+    )
+    .execute(&state.pool)
+    .await
+    .server_error()?;
+
+    tracing::debug!("user({user_id}) unarchived conversation with user({other_user_id})");
+
+    Ok(refresh())
+}
+
+pub async fn pin(
+    Path(other_user_name): Path<String>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<HeaderMap, (StatusCode, String)> {
+    set_pinned(user_id, &other_user_name, true, &state.pool).await
+}
+
+pub async fn unpin(
+    Path(other_user_name): Path<String>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<HeaderMap, (StatusCode, String)> {
+    set_pinned(user_id, &other_user_name, false, &state.pool).await
+}
+
+async fn set_pinned(
+    user_id: i32,
+    other_user_name: &str,
+    pinned: bool,
+    pool: &PgPool,
+) -> Result<HeaderMap, (StatusCode, String)> {
+    let other_user_id = find_user_id(other_user_name, pool).await?;
+
+    sqlx::query!(
+        "INSERT INTO conversation_settings(user_id, other_user_id, pinned) VALUES ($1, $2, $3)
+        ON CONFLICT (user_id, other_user_id) DO UPDATE SET pinned = EXCLUDED.pinned",
+        user_id,
+        other_user_id,
+        pinned
+    )
+    .execute(pool)
+    .await
+    .server_error()?;
+
+    tracing::debug!("user({user_id}) set pinned to {pinned} for user({other_user_id})");
+
+    Ok(refresh())
+}
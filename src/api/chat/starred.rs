@@ -0,0 +1,57 @@
+use axum::extract::{Path, State};
+use http::StatusCode;
+
+use super::message_conversation;
+use crate::{
+    data::app_state::AppState,
+    tasks::now,
+    utils::{auth_layer::ExtractActivatedAuth, ToServerError},
+};
+
+/// Stars are private, only the user who starred a message sees it.
+pub async fn star_message(
+    Path(message_id): Path<i32>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let conversation = message_conversation(message_id, user_id, &state.pool).await?;
+
+    sqlx::query!(
+        "INSERT INTO starred_messages(user_id, message_id, starred_at) VALUES ($1, $2, $3) ON CONFLICT (user_id, message_id) DO NOTHING",
+        user_id,
+        message_id,
+        now()
+    )
+    .execute(&state.pool)
+    .await
+    .server_error()?;
+
+    tracing::debug!("user({user_id}) starred message({message_id})");
+
+    state.message_sent.send_replace(conversation);
+
+    Ok(StatusCode::OK)
+}
+
+pub async fn unstar_message(
+    Path(message_id): Path<i32>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let conversation = message_conversation(message_id, user_id, &state.pool).await?;
+
+    sqlx::query!(
+        "DELETE FROM starred_messages WHERE user_id = $1 AND message_id = $2",
+        user_id,
+        message_id
+    )
+    .execute(&state.pool)
+    .await
+    .server_error()?;
+
+    tracing::debug!("user({user_id}) unstarred message({message_id})");
+
+    state.message_sent.send_replace(conversation);
+
+    Ok(StatusCode::OK)
+}
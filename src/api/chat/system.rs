@@ -0,0 +1,45 @@
+use sqlx::{Postgres, Transaction};
+
+use super::disappearing::RetentionPeriod;
+use crate::tasks::now;
+
+/// Something that happened in a conversation, the server puts these in the timeline
+/// where they show as the name of whoever caused it followed by the text.
+pub enum ConversationEvent {
+    DisappearingMessagesOn(RetentionPeriod),
+    DisappearingMessagesOff,
+}
+
+impl ConversationEvent {
+    fn text(&self) -> String {
+        match self {
+            ConversationEvent::DisappearingMessagesOn(period) => format!(
+                "turned on disappearing messages. New messages are deleted after {}.",
+                period.description()
+            ),
+            ConversationEvent::DisappearingMessagesOff => {
+                String::from("turned off disappearing messages.")
+            }
+        }
+    }
+}
+
+/// System messages are plain text, they have no markdown, mentions, attachments or timer
+/// and nobody is notified about them.
+pub async fn insert_system_message(
+    actor_id: i32,
+    other_user_id: i32,
+    event: ConversationEvent,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> anyhow::Result<i32> {
+    Ok(sqlx::query!(
+        "INSERT INTO chat_messages(sender_id, recipient_id, msg, sent_at, kind) VALUES ($1, $2, $3, $4, 'system') RETURNING id",
+        actor_id,
+        other_user_id,
+        event.text(),
+        now()
+    )
+    .fetch_one(&mut **transaction)
+    .await?
+    .id)
+}
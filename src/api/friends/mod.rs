@@ -0,0 +1,287 @@
+use askama::Template;
+use axum::{
+    extract::{Path, State},
+    routing::post,
+    Router,
+};
+use http::StatusCode;
+use sqlx::PgPool;
+
+use crate::{
+    data::app_state::AppState,
+    notifications::{notify, NotificationKind, NotificationSubject},
+    tasks::now,
+    utils::{
+        auth_layer::ExtractActivatedAuth, relationship::Relationship, username::Username,
+        ToServerError,
+    },
+};
+
+pub fn friends_routes() -> Router<AppState> {
+    Router::new()
+        .route("/request/:username", post(send_request))
+        .route("/accept/:username", post(accept_request))
+        .route("/decline/:username", post(decline_request))
+        .route("/remove/:username", post(remove_friend))
+        .route("/block/:username", post(block))
+        .route("/unblock/:username", post(unblock))
+}
+
+#[derive(Template)]
+#[template(path = "components/friend_actions.html")]
+pub struct FriendActionsTemplate {
+    pub name: Username,
+    pub relationship: Relationship,
+}
+
+async fn other_user_id(
+    user_id: i32,
+    username: &str,
+    pool: &PgPool,
+) -> Result<i32, (StatusCode, String)> {
+    let other_user_id = sqlx::query!(
+        "SELECT id FROM users WHERE username = $1 AND deleted_at IS NULL",
+        username
+    )
+    .fetch_optional(pool)
+    .await
+    .server_error()?
+    .ok_or((StatusCode::BAD_REQUEST, String::from("Bad Request")))?
+    .id;
+
+    if other_user_id == user_id {
+        tracing::debug!("user({user_id}) tried to change relationship with themself");
+        return Err((StatusCode::BAD_REQUEST, String::from("Bad Request")));
+    }
+
+    Ok(other_user_id)
+}
+
+async fn actions(
+    user_id: i32,
+    other_user_id: i32,
+    pool: &PgPool,
+) -> Result<FriendActionsTemplate, (StatusCode, String)> {
+    Ok(FriendActionsTemplate {
+        name: Username::new_from_id(other_user_id, pool)
+            .await
+            .server_error()?,
+        relationship: Relationship::between(user_id, other_user_id, pool)
+            .await
+            .server_error()?,
+    })
+}
+
+async fn make_friends(user_id: i32, other_user_id: i32, pool: &PgPool) -> anyhow::Result<()> {
+    let mut transaction = pool.begin().await?;
+
+    sqlx::query!(
+        "DELETE FROM friend_requests WHERE (sender_id = $1 AND recipient_id = $2) OR (sender_id = $2 AND recipient_id = $1)",
+        user_id,
+        other_user_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO friends(user_id, friend_id, since) VALUES ($1, $2, $3), ($2, $1, $3) ON CONFLICT DO NOTHING",
+        user_id,
+        other_user_id,
+        now()
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+async fn send_request(
+    Path(username): Path<String>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<FriendActionsTemplate, (StatusCode, String)> {
+    let other_user_id = other_user_id(user_id, &username, &state.pool).await?;
+
+    match Relationship::between(user_id, other_user_id, &state.pool)
+        .await
+        .server_error()?
+    {
+        Relationship::Stranger => {
+            // a double click sends the same request twice
+            let sent = sqlx::query!(
+                "INSERT INTO friend_requests(sender_id, recipient_id, sent_at) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+                user_id,
+                other_user_id,
+                now()
+            )
+            .execute(&state.pool)
+            .await
+            .server_error()?
+            .rows_affected()
+                > 0;
+
+            tracing::debug!("user({user_id}) sent friend request to user({other_user_id})");
+
+            if sent {
+                notify(
+                    &state,
+                    other_user_id,
+                    NotificationKind::FriendRequest,
+                    NotificationSubject {
+                        actor_id: Some(user_id),
+                        ..Default::default()
+                    },
+                )
+                .await;
+            }
+        }
+        Relationship::RequestReceived => {
+            make_friends(user_id, other_user_id, &state.pool)
+                .await
+                .server_error()?;
+
+            tracing::debug!("user({user_id}) and user({other_user_id}) requested each other");
+        }
+        Relationship::Friends | Relationship::RequestSent => {}
+        Relationship::Blocked | Relationship::BlockedBy => {
+            return Err((StatusCode::FORBIDDEN, String::from("Forbidden")));
+        }
+    }
+
+    actions(user_id, other_user_id, &state.pool).await
+}
+
+async fn accept_request(
+    Path(username): Path<String>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<FriendActionsTemplate, (StatusCode, String)> {
+    let other_user_id = other_user_id(user_id, &username, &state.pool).await?;
+
+    if Relationship::between(user_id, other_user_id, &state.pool)
+        .await
+        .server_error()?
+        != Relationship::RequestReceived
+    {
+        tracing::debug!("user({user_id}) has no request from user({other_user_id}) to accept");
+        return Err((StatusCode::BAD_REQUEST, String::from("Bad Request")));
+    }
+
+    make_friends(user_id, other_user_id, &state.pool)
+        .await
+        .server_error()?;
+
+    tracing::debug!("user({user_id}) accepted friend request from user({other_user_id})");
+
+    actions(user_id, other_user_id, &state.pool).await
+}
+
+async fn decline_request(
+    Path(username): Path<String>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<FriendActionsTemplate, (StatusCode, String)> {
+    let other_user_id = other_user_id(user_id, &username, &state.pool).await?;
+
+    // also used to cancel a request the user sent
+    sqlx::query!(
+        "DELETE FROM friend_requests WHERE (sender_id = $1 AND recipient_id = $2) OR (sender_id = $2 AND recipient_id = $1)",
+        user_id,
+        other_user_id
+    )
+    .execute(&state.pool)
+    .await
+    .server_error()?;
+
+    tracing::debug!("user({user_id}) declined friend request with user({other_user_id})");
+
+    actions(user_id, other_user_id, &state.pool).await
+}
+
+async fn remove_friend(
+    Path(username): Path<String>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<FriendActionsTemplate, (StatusCode, String)> {
+    let other_user_id = other_user_id(user_id, &username, &state.pool).await?;
+
+    sqlx::query!(
+        "DELETE FROM friends WHERE (user_id = $1 AND friend_id = $2) OR (user_id = $2 AND friend_id = $1)",
+        user_id,
+        other_user_id
+    )
+    .execute(&state.pool)
+    .await
+    .server_error()?;
+
+    tracing::debug!("user({user_id}) removed friend user({other_user_id})");
+
+    actions(user_id, other_user_id, &state.pool).await
+}
+
+async fn block(
+    Path(username): Path<String>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<FriendActionsTemplate, (StatusCode, String)> {
+    let other_user_id = other_user_id(user_id, &username, &state.pool).await?;
+
+    let mut transaction = state.pool.begin().await.server_error()?;
+
+    sqlx::query!(
+        "DELETE FROM friends WHERE (user_id = $1 AND friend_id = $2) OR (user_id = $2 AND friend_id = $1)",
+        user_id,
+        other_user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .server_error()?;
+
+    sqlx::query!(
+        "DELETE FROM friend_requests WHERE (sender_id = $1 AND recipient_id = $2) OR (sender_id = $2 AND recipient_id = $1)",
+        user_id,
+        other_user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .server_error()?;
+
+    sqlx::query!(
+        "INSERT INTO blocked_users(user_id, blocked_id, blocked_at) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+        user_id,
+        other_user_id,
+        now()
+    )
+    .execute(&mut *transaction)
+    .await
+    .server_error()?;
+
+    transaction.commit().await.server_error()?;
+
+    tracing::debug!("user({user_id}) blocked user({other_user_id})");
+
+    actions(user_id, other_user_id, &state.pool).await
+}
+
+async fn unblock(
+    Path(username): Path<String>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<FriendActionsTemplate, (StatusCode, String)> {
+    let other_user_id = other_user_id(user_id, &username, &state.pool).await?;
+
+    sqlx::query!(
+        "DELETE FROM blocked_users WHERE user_id = $1 AND blocked_id = $2",
+        user_id,
+        other_user_id
+    )
+    .execute(&state.pool)
+    .await
+    .server_error()?;
+
+    tracing::debug!("user({user_id}) unblocked user({other_user_id})");
+
+    actions(user_id, other_user_id, &state.pool).await
+}
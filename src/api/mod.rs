@@ -1,6 +1,8 @@
+pub mod account;
 pub mod auth;
 pub mod chat;
-pub mod account;
+pub mod friends;
+pub mod notifications;
 
 use axum::Router;
 use http::StatusCode;
@@ -12,6 +14,8 @@ pub fn api_routes() -> Router<AppState> {
         .nest("/auth", auth::auth_routes())
         .nest("/chat", chat::chat_routes())
         .nest("/account", account::account_details_uris())
+        .nest("/friends", friends::friends_routes())
+        .nest("/notifications", notifications::notification_routes())
         .fallback(not_found)
 }
 
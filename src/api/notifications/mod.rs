@@ -0,0 +1,316 @@
+use std::time::Duration;
+
+use askama::Template;
+use axum::{
+    extract::{Path, State},
+    response::{sse::Event, Redirect, Sse},
+    routing::{get, post, put},
+    Form, Json, Router,
+};
+use futures::stream::Stream;
+use http::{HeaderMap, StatusCode};
+use sqlx::PgPool;
+
+use crate::{
+    data::app_state::AppState,
+    notifications::{describe, link, unread_count, NotificationKind, OnlineGuard},
+    tasks::now,
+    utils::{
+        auth_layer::ExtractActivatedAuth, refresh, relative_time::relative_time,
+        username::Username, ToServerError,
+    },
+};
+
+const MAX_LISTED_NOTIFICATIONS: i64 = 30;
+
+pub fn notification_routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(notification_list))
+        .route("/read", post(mark_all_read))
+        .route("/:id/open", get(open_notification))
+        .route("/preferences", put(change_preferences))
+        .route("/event", get(sse_notifications))
+        .route("/push/public_key", get(push_public_key))
+        .route("/push/subscribe", post(push_subscribe))
+        .route("/push/unsubscribe", post(push_unsubscribe))
+}
+
+#[derive(Template)]
+#[template(path = "components/notification_list.html")]
+pub struct NotificationListTemplate {
+    notifications: Vec<NotificationEntry>,
+}
+
+#[derive(Template)]
+#[template(path = "components/notification_count.html")]
+pub struct NotificationCountTemplate {
+    pub unread: i64,
+}
+
+struct NotificationEntry {
+    id: i32,
+    text: String,
+    created_ago: String,
+    read: bool,
+}
+
+async fn notification_list(
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<NotificationListTemplate, (StatusCode, String)> {
+    list(user_id, &state.pool).await
+}
+
+async fn list(
+    user_id: i32,
+    pool: &PgPool,
+) -> Result<NotificationListTemplate, (StatusCode, String)> {
+    let notifications = sqlx::query!(
+        r#"SELECT notifications.id, kind, detail, notifications.created_at, read_at IS NOT NULL AS "read!",
+            users.username AS "username?", users.display_name, users.profile_picture_key
+        FROM notifications
+        LEFT JOIN users ON users.id = notifications.actor_id
+        WHERE user_id = $1
+        ORDER BY notifications.created_at DESC, notifications.id DESC
+        LIMIT $2"#,
+        user_id,
+        MAX_LISTED_NOTIFICATIONS
+    )
+    .fetch_all(pool)
+    .await
+    .server_error()?
+    .into_iter()
+    .filter_map(|rec| {
+        let actor = rec
+            .username
+            .map(|username| Username::new(username, rec.display_name, rec.profile_picture_key));
+
+        Some(NotificationEntry {
+            id: rec.id,
+            text: describe(
+                NotificationKind::from_name(&rec.kind)?,
+                actor.as_ref(),
+                rec.detail.as_deref(),
+            ),
+            created_ago: relative_time(rec.created_at),
+            read: rec.read,
+        })
+    })
+    .collect();
+
+    Ok(NotificationListTemplate { notifications })
+}
+
+async fn mark_all_read(
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<NotificationListTemplate, (StatusCode, String)> {
+    sqlx::query!(
+        "UPDATE notifications SET read_at = $1 WHERE user_id = $2 AND read_at IS NULL",
+        now(),
+        user_id
+    )
+    .execute(&state.pool)
+    .await
+    .server_error()?;
+
+    tracing::debug!("user({user_id}) read all notifications");
+
+    state.notification_sent.send_replace(user_id);
+
+    list(user_id, &state.pool).await
+}
+
+/// Marks the notification as read and goes to what it is about.
+async fn open_notification(
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<Redirect, (StatusCode, String)> {
+    let notification = sqlx::query!(
+        r#"UPDATE notifications SET read_at = COALESCE(read_at, $1)
+        WHERE id = $2 AND user_id = $3
+        RETURNING kind, message_id, actor_id"#,
+        now(),
+        id,
+        user_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .server_error()?
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        String::from("Notification Not Found"),
+    ))?;
+
+    state.notification_sent.send_replace(user_id);
+
+    let actor = match notification.actor_id {
+        Some(actor_id) => Some(
+            Username::new_from_id(actor_id, &state.pool)
+                .await
+                .server_error()?,
+        ),
+        None => None,
+    };
+
+    let location = match NotificationKind::from_name(&notification.kind) {
+        Some(kind) => link(kind, actor.as_ref(), notification.message_id),
+        None => String::from("/"),
+    };
+
+    Ok(Redirect::to(&location))
+}
+
+async fn change_preferences(
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+    Form(form): Form<Vec<(String, String)>>,
+) -> Result<HeaderMap, (StatusCode, String)> {
+    // unchecked boxes aren't sent so every kind that is missing is turned off
+    let mut transaction = state.pool.begin().await.server_error()?;
+
+    for kind in NotificationKind::ALL {
+        let enabled = form.iter().any(|(name, _)| name == kind.name());
+
+        sqlx::query!(
+            "INSERT INTO notification_preferences(user_id, kind, enabled) VALUES ($1, $2, $3)
+            ON CONFLICT (user_id, kind) DO UPDATE SET enabled = EXCLUDED.enabled",
+            user_id,
+            kind.name(),
+            enabled
+        )
+        .execute(&mut *transaction)
+        .await
+        .server_error()?;
+    }
+
+    transaction.commit().await.server_error()?;
+
+    tracing::debug!("user({user_id}) changed notification preferences");
+
+    Ok(refresh())
+}
+
+/// Keeps the unread count on the bell up to date. While this is open the user counts as online.
+async fn sse_notifications(
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+) -> Result<Sse<impl Stream<Item = Result<Event, anyhow::Error>>>, (StatusCode, String)> {
+    tracing::debug!("sse notifications start for user({user_id})");
+
+    let mut listener = state.notification_sent.subscribe();
+
+    let stream = async_stream::stream! {
+        let _online = OnlineGuard::new(state.clone(), user_id);
+
+        loop {
+            listener.changed().await?;
+            let notified_user_id = *listener.borrow();
+
+            if notified_user_id == user_id {
+                let count = NotificationCountTemplate {
+                    unread: unread_count(user_id, &state.pool).await?,
+                };
+
+                let html = count.render()?.replace(&['\n', '\r'], "");
+
+                yield Ok(Event::default().event("notifications").data(html));
+            }
+        }
+    };
+
+    Ok(Sse::new(stream).keep_alive(
+        axum::response::sse::KeepAlive::new()
+            .interval(Duration::from_secs(1))
+            .text("keep-alive-text"),
+    ))
+}
+
+/// The VAPID key browsers subscribe with, 404 when push isn't set up on this server.
+async fn push_public_key(State(state): State<AppState>) -> Result<String, (StatusCode, String)> {
+    state
+        .web_push
+        .as_ref()
+        .map(|web_push| web_push.public_key().to_owned())
+        .ok_or((StatusCode::NOT_FOUND, String::from("Push Not Enabled")))
+}
+
+/// The `PushSubscription` from the browser, serialized with `toJSON`.
+#[derive(serde::Deserialize)]
+pub struct PushSubscriptionJson {
+    endpoint: String,
+    keys: PushSubscriptionKeys,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PushSubscriptionKeys {
+    p256dh: String,
+    auth: String,
+}
+
+async fn push_subscribe(
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+    Json(subscription): Json<PushSubscriptionJson>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let web_push = state
+        .web_push
+        .as_ref()
+        .ok_or((StatusCode::NOT_FOUND, String::from("Push Not Enabled")))?;
+
+    if !web_push.accepts_endpoint(&subscription.endpoint) {
+        return Err((StatusCode::BAD_REQUEST, String::from("Bad Request")));
+    }
+
+    // an endpoint belongs to whoever subscribed it first, another user on the same browser gets a new one
+    let saved = sqlx::query!(
+        "INSERT INTO push_subscriptions(user_id, endpoint, p256dh, auth, created_at) VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (endpoint) DO UPDATE SET p256dh = EXCLUDED.p256dh, auth = EXCLUDED.auth
+        WHERE push_subscriptions.user_id = EXCLUDED.user_id",
+        user_id,
+        subscription.endpoint,
+        subscription.keys.p256dh,
+        subscription.keys.auth,
+        now()
+    )
+    .execute(&state.pool)
+    .await
+    .server_error()?
+    .rows_affected();
+
+    if saved == 0 {
+        return Err((
+            StatusCode::CONFLICT,
+            String::from("Subscribed By Another User"),
+        ));
+    }
+
+    tracing::debug!("user({user_id}) subscribed to push");
+
+    Ok(StatusCode::CREATED)
+}
+
+#[derive(serde::Deserialize)]
+pub struct PushUnsubscribeJson {
+    endpoint: String,
+}
+
+async fn push_unsubscribe(
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+    Json(subscription): Json<PushUnsubscribeJson>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    sqlx::query!(
+        "DELETE FROM push_subscriptions WHERE user_id = $1 AND endpoint = $2",
+        user_id,
+        subscription.endpoint
+    )
+    .execute(&state.pool)
+    .await
+    .server_error()?;
+
+    tracing::debug!("user({user_id}) unsubscribed from push");
+
+    Ok(StatusCode::OK)
+}
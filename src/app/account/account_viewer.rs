@@ -1,10 +1,79 @@
 use askama::Template;
 use http::StatusCode;
+use sqlx::PgPool;
 
-pub async fn account_viewer_page() -> Result<AccountViewerTemplate, (StatusCode, String)> {
-    Ok(AccountViewerTemplate)
+use crate::utils::{
+    relationship::{can_message, Relationship},
+    username::Username,
+    ToServerError,
+};
+
+pub async fn account_viewer_page(
+    viewer_id: Option<i32>,
+    account_username: &str,
+    pool: &PgPool,
+) -> Result<AccountViewerTemplate, (StatusCode, String)> {
+    let account = sqlx::query!(
+        "SELECT id, username, display_name, profile_picture_key, bio, status, created_at, friends_only_profile FROM users WHERE username = $1 AND activated",
+        account_username
+    )
+    .fetch_optional(pool)
+    .await
+    .server_error()?
+    .ok_or((StatusCode::NOT_FOUND, String::from("User Not Found")))?;
+
+    let (relationship, can_message) = match viewer_id {
+        Some(viewer_id) => (
+            Some(
+                Relationship::between(viewer_id, account.id, pool)
+                    .await
+                    .server_error()?,
+            ),
+            can_message(viewer_id, account.id, pool)
+                .await
+                .server_error()?,
+        ),
+        None => (None, false),
+    };
+
+    // looks the same as an account that doesn't exist, so being blocked isn't given away
+    if relationship == Some(Relationship::BlockedBy) {
+        return Err((StatusCode::NOT_FOUND, String::from("User Not Found")));
+    }
+
+    // strangers and logged out visitors only get the name and picture of friends only profiles
+    let details_hidden = match relationship {
+        Some(Relationship::Blocked) => true,
+        Some(Relationship::Friends) => false,
+        _ => account.friends_only_profile,
+    };
+
+    Ok(AccountViewerTemplate {
+        name: Username::new(
+            account.username,
+            account.display_name,
+            account.profile_picture_key,
+        ),
+        bio: account.bio.filter(|_| !details_hidden),
+        status: account.status.filter(|_| !details_hidden),
+        member_since: account
+            .created_at
+            .filter(|_| !details_hidden)
+            .map(|created_at| format!("{} {}", created_at.month(), created_at.year())),
+        relationship,
+        can_message,
+        details_hidden,
+    })
 }
 
-#[derive(Template, Default)]
+#[derive(Template)]
 #[template(path = "account_viewer.html")]
-pub struct AccountViewerTemplate ;
+pub struct AccountViewerTemplate {
+    name: Username,
+    bio: Option<String>,
+    status: Option<String>,
+    member_since: Option<String>,
+    relationship: Option<Relationship>,
+    can_message: bool,
+    details_hidden: bool,
+}
@@ -4,8 +4,19 @@ use http::StatusCode;
 use sqlx::PgPool;
 
 use crate::{
+    api::account::{
+        change_profile::{MAX_BIO_LENGTH, MAX_STATUS_LENGTH},
+        email_digest::DigestFrequency,
+    },
     data::app_state::AppState,
-    utils::{username::Username, ToServerError, auth_layer::ExtractOptionalActivatedAuth},
+    notifications::{is_enabled, NotificationKind},
+    utils::{
+        auth_layer::ExtractOptionalActivatedAuth,
+        relationship::Relationship,
+        relative_time::{full_date, relative_time},
+        username::Username,
+        ToServerError,
+    },
 };
 
 use self::account_viewer::{account_viewer_page, AccountViewerTemplate};
@@ -17,7 +28,6 @@ pub async fn account_route(
     State(state): State<AppState>,
     ExtractOptionalActivatedAuth(user_id): ExtractOptionalActivatedAuth,
 ) -> Result<Result<EditableAccountTemplate, AccountViewerTemplate>, (StatusCode, String)> {
-
     match user_id {
         Some(user_id) => {
             let username = sqlx::query!("SELECT username FROM users WHERE id = $1", user_id)
@@ -29,10 +39,20 @@ pub async fn account_route(
             if username == account_username {
                 Ok(Ok(editable_account_page(user_id, &state.pool).await?))
             } else {
-                Ok(Err(account_viewer_page().await?))
+                Ok(Err(account_viewer_page(
+                    Some(user_id),
+                    &account_username,
+                    &state.pool,
+                )
+                .await?))
             }
         }
-        None => Ok(Err(account_viewer_page().await?)),
+        None => Ok(Err(account_viewer_page(
+            None,
+            &account_username,
+            &state.pool,
+        )
+        .await?)),
     }
 }
 
@@ -40,8 +60,76 @@ async fn editable_account_page(
     user_id: i32,
     pool: &PgPool,
 ) -> Result<EditableAccountTemplate, (StatusCode, String)> {
+    let profile = sqlx::query!(
+        "SELECT friends_only_messages, bio, status, friends_only_profile FROM users WHERE id = $1",
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .server_error()?;
+
+    let blocked = sqlx::query!(
+        "SELECT username, display_name, profile_picture_key FROM blocked_users JOIN users ON users.id = blocked_users.blocked_id WHERE user_id = $1 ORDER BY blocked_at DESC",
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .server_error()?
+    .into_iter()
+    .map(|rec| Username::new(rec.username, rec.display_name, rec.profile_picture_key))
+    .collect();
+
+    let deletion_date = sqlx::query!(
+        "SELECT delete_after FROM account_deletions WHERE user_id = $1",
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .server_error()?
+    .map(|rec| full_date(rec.delete_after));
+
+    let export = sqlx::query!(
+        r#"SELECT id, requested_at, finished_at IS NOT NULL AS "ready!", failed FROM data_exports WHERE user_id = $1 ORDER BY id DESC LIMIT 1"#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .server_error()?
+    .map(|rec| DataExportStatus {
+        id: rec.id,
+        requested: relative_time(rec.requested_at),
+        ready: rec.ready,
+        failed: rec.failed,
+    });
+
+    let mut notification_preferences = vec![];
+
+    for kind in NotificationKind::ALL {
+        notification_preferences
+            .push((kind, is_enabled(user_id, kind, pool).await.server_error()?));
+    }
+
+    let digest_frequency = sqlx::query!(
+        "SELECT frequency FROM email_digests WHERE user_id = $1",
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .server_error()?
+    .and_then(|rec| DigestFrequency::from_name(&rec.frequency))
+    .unwrap_or(DigestFrequency::DEFAULT);
+
     Ok(EditableAccountTemplate {
         username: Username::new_from_id(user_id, pool).await.server_error()?,
+        friends_only_messages: profile.friends_only_messages,
+        bio: profile.bio.unwrap_or_default(),
+        status: profile.status.unwrap_or_default(),
+        friends_only_profile: profile.friends_only_profile,
+        blocked,
+        deletion_date,
+        export,
+        notification_preferences,
+        digest_frequency,
     })
 }
 
@@ -49,4 +137,34 @@ async fn editable_account_page(
 #[template(path = "editable_account.html")]
 pub struct EditableAccountTemplate {
     username: Username,
+    friends_only_messages: bool,
+    bio: String,
+    status: String,
+    friends_only_profile: bool,
+    blocked: Vec<Username>,
+    deletion_date: Option<String>,
+    export: Option<DataExportStatus>,
+    notification_preferences: Vec<(NotificationKind, bool)>,
+    digest_frequency: DigestFrequency,
+}
+
+struct DataExportStatus {
+    id: i32,
+    requested: String,
+    ready: bool,
+    failed: bool,
+}
+
+impl EditableAccountTemplate {
+    fn max_bio_length(&self) -> usize {
+        MAX_BIO_LENGTH
+    }
+
+    fn max_status_length(&self) -> usize {
+        MAX_STATUS_LENGTH
+    }
+
+    fn digest_frequencies(&self) -> [DigestFrequency; 4] {
+        DigestFrequency::ALL
+    }
 }
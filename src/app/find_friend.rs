@@ -1,10 +1,16 @@
 use askama::Template;
-use axum::{extract::State, Form};
+use axum::{
+    extract::{Path, Query, State},
+    Form,
+};
 use http::StatusCode;
 
 use crate::{
     data::app_state::AppState,
-    utils::{username::Username, ToServerError, auth_layer::ExtractActivatedAuth},
+    utils::{
+        auth_layer::ExtractActivatedAuth, mentions::partial_mention, relationship::Relationship,
+        username::Username, ToServerError,
+    },
 };
 
 pub async fn find_friend_modal() -> FindFriendModalTemplate {
@@ -15,9 +21,12 @@ pub async fn find_friend_modal() -> FindFriendModalTemplate {
 #[template(path = "components/find_friend_modal.html")]
 pub struct FindFriendModalTemplate;
 
+const PAGE_SIZE: i64 = 20;
+
 #[derive(serde::Deserialize)]
 pub struct FindFriendForm {
     search: String,
+    page: Option<i64>,
 }
 
 pub async fn find_friend_list(
@@ -25,28 +34,153 @@ pub async fn find_friend_list(
     ExtractActivatedAuth(user_id): ExtractActivatedAuth,
     Form(form): Form<FindFriendForm>,
 ) -> Result<FindFriendListTemplate, (StatusCode, String)> {
-    let search = form.search;
+    let search = form.search.trim().to_lowercase();
+    let page = form.page.unwrap_or_default().max(0);
+    let offset = page * PAGE_SIZE;
 
-    tracing::debug!("search from user({}) for friend with: {}", user_id, search);
+    tracing::debug!(
+        "search from user({}) for friend with: {} page: {}",
+        user_id,
+        search,
+        page
+    );
 
-    let name_list = sqlx::query!(
-        "SELECT username, display_name FROM users WHERE SUBSTRING(username for $2) = $1 AND id != $3 LIMIT 100",
+    // one extra row is fetched to know if there is another page
+    let mut name_list = sqlx::query!(
+        "SELECT username, display_name, profile_picture_key,
+            EXISTS(SELECT 1 FROM friends WHERE user_id = $1 AND friend_id = users.id) AS friends,
+            EXISTS(SELECT 1 FROM friend_requests WHERE sender_id = $1 AND recipient_id = users.id) AS request_sent,
+            EXISTS(SELECT 1 FROM friend_requests WHERE sender_id = users.id AND recipient_id = $1) AS request_received
+        FROM users
+        WHERE id != $1 AND activated = true
+            AND NOT EXISTS(
+                SELECT 1 FROM blocked_users
+                WHERE (user_id = users.id AND blocked_id = $1) OR (user_id = $1 AND blocked_id = users.id)
+            )
+            AND (
+                starts_with(lower(username), $2)
+                OR starts_with(lower(COALESCE(display_name, '')), $2)
+                OR $2 <% lower(username)
+                OR $2 <% lower(COALESCE(display_name, ''))
+            )
+        ORDER BY
+            (starts_with(lower(username), $2) OR starts_with(lower(COALESCE(display_name, '')), $2)) DESC,
+            GREATEST(
+                word_similarity($2, lower(username)),
+                word_similarity($2, lower(COALESCE(display_name, '')))
+            ) DESC,
+            username
+        LIMIT $3 OFFSET $4",
+        user_id,
         search,
-        search.len() as i32,
-        user_id
+        PAGE_SIZE + 1,
+        offset
     )
     .fetch_all(&state.pool)
     .await
     .server_error()?
     .into_iter()
-    .map(|rec| Username::new(rec.username, rec.display_name))
+    .map(|rec| {
+        (
+            Username::new(rec.username, rec.display_name, rec.profile_picture_key),
+            Relationship::from_flags(
+                false,
+                false,
+                rec.friends.unwrap_or_default(),
+                rec.request_sent.unwrap_or_default(),
+                rec.request_received.unwrap_or_default(),
+            ),
+        )
+    })
     .collect::<Vec<_>>();
 
-    Ok(FindFriendListTemplate { name_list })
+    let next_page = if name_list.len() as i64 > PAGE_SIZE {
+        name_list.truncate(PAGE_SIZE as usize);
+        Some(page + 1)
+    } else {
+        None
+    };
+
+    Ok(FindFriendListTemplate {
+        name_list,
+        offset: offset as usize,
+        next_page,
+    })
 }
 
 #[derive(Template)]
 #[template(path = "components/find_friend_list.html")]
 pub struct FindFriendListTemplate {
-    pub name_list: Vec<Username>,
+    pub name_list: Vec<(Username, Relationship)>,
+    pub offset: usize,
+    pub next_page: Option<i64>,
+}
+
+const MAX_MENTION_SUGGESTIONS: i64 = 5;
+
+#[derive(serde::Deserialize)]
+pub struct MentionQuery {
+    message: String,
+}
+
+/// Suggests people in the conversation for the mention being typed at the end of the message.
+pub async fn mention_suggestions(
+    Path(recipient_name): Path<String>,
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+    Query(query): Query<MentionQuery>,
+) -> Result<MentionSuggestionsTemplate, (StatusCode, String)> {
+    let Some(partial) = partial_mention(&query.message) else {
+        return Ok(MentionSuggestionsTemplate {
+            suggestions: vec![],
+        });
+    };
+    let search = partial.to_lowercase();
+
+    let participants = sqlx::query!("SELECT id FROM users WHERE username = $1", recipient_name)
+        .fetch_optional(&state.pool)
+        .await
+        .server_error()?
+        .map(|rec| vec![rec.id])
+        .unwrap_or_default();
+
+    tracing::debug!("mention suggestions for user({user_id}) with: {search}");
+
+    let suggestions = sqlx::query!(
+        "SELECT username, display_name, profile_picture_key
+        FROM users
+        WHERE id = ANY($1) AND activated = true
+            AND (
+                $2 = ''
+                OR starts_with(lower(username), $2)
+                OR starts_with(lower(COALESCE(display_name, '')), $2)
+                OR $2 <% lower(username)
+                OR $2 <% lower(COALESCE(display_name, ''))
+            )
+        ORDER BY
+            (starts_with(lower(username), $2) OR starts_with(lower(COALESCE(display_name, '')), $2)) DESC,
+            GREATEST(
+                word_similarity($2, lower(username)),
+                word_similarity($2, lower(COALESCE(display_name, '')))
+            ) DESC,
+            username
+        LIMIT $3",
+        &participants,
+        search,
+        MAX_MENTION_SUGGESTIONS
+    )
+    .fetch_all(&state.pool)
+    .await
+    .server_error()?
+    .into_iter()
+    .map(|rec| Username::new(rec.username, rec.display_name, rec.profile_picture_key))
+    .collect();
+
+    Ok(MentionSuggestionsTemplate { suggestions })
+}
+
+#[derive(Template)]
+#[template(path = "components/mention_suggestions.html")]
+pub struct MentionSuggestionsTemplate {
+    pub suggestions: Vec<Username>,
 }
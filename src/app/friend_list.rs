@@ -1,95 +1,193 @@
-use std::collections::HashMap;
-
+use askama::Template;
 use sqlx::PgPool;
+use time::PrimitiveDateTime;
 
-use crate::utils::username::Username;
+use crate::{
+    tasks::now,
+    utils::{percent_encode, relative_time::relative_time, username::Username},
+};
 
 pub struct FiendListInfo {
-    pub friends: Vec<Username>,
+    pub user_id: i32,
+    pub selected: Option<String>,
+    pub friends: Vec<FriendListEntry>,
+    /// Archived conversations without a message since they were archived.
+    pub archived: Vec<FriendListEntry>,
+    pub requests: Vec<Username>,
 }
 
-impl FiendListInfo {
-    // this code is terible find a way to do this with sql
-    pub async fn new(user_id: i32, pool: &PgPool) -> anyhow::Result<Self> {
-        let friends = get_friends(user_id, pool).await?;
+#[derive(Template)]
+#[template(path = "components/friend_list_entries.html")]
+pub struct FriendListEntries {
+    pub friend_list_info: FiendListInfo,
+}
 
-        let mut friend_names = vec![];
+pub struct FriendListEntry {
+    pub name: Username,
+    pub last_sender_id: Option<i32>,
+    pub last_message: Option<String>,
+    pub last_sent_at: Option<PrimitiveDateTime>,
+    /// The last message is a system message, its text follows the sender's name.
+    pub last_system: bool,
+    /// Shown instead of the last message until it is sent.
+    pub draft: Option<String>,
+    pub unseen_mentions: i64,
+    pub unread: i64,
+    pub muted: bool,
+    pub archived: bool,
+    pub pinned: bool,
+}
 
-        for friend_id in friends {
-            let friend_name = sqlx::query!(
-                "SELECT username, display_name FROM users WHERE id = $1;",
-                friend_id
-            )
-            .fetch_one(pool)
-            .await?;
-            friend_names.push(Username::new(
-                friend_name.username,
-                friend_name.display_name,
-            ))
-        }
+impl FriendListEntry {
+    pub fn last_sent_by(&self, user_id: &i32) -> bool {
+        self.last_sender_id == Some(*user_id)
+    }
 
-        Ok(Self {
-            friends: friend_names,
-        })
+    pub fn last_sent_ago(&self) -> Option<String> {
+        self.last_sent_at.map(relative_time)
     }
 }
 
-// this code is terible find a way to do this with sql
-pub async fn get_friends(user_id: i32, pool: &PgPool) -> anyhow::Result<Vec<i32>> {
-    let mut friends_vec = sqlx::query!(
-        "SELECT recipient_id, sent_at FROM chat_messages WHERE sender_id = $1",
-        user_id
-    )
-    .fetch_all(pool)
-    .await?
-    .into_iter()
-    .map(|rec| (rec.recipient_id, rec.sent_at))
-    .collect::<Vec<_>>();
+impl FiendListInfo {
+    pub fn is_selected(&self, username: impl AsRef<str>) -> bool {
+        self.selected.as_deref() == Some(username.as_ref())
+    }
+
+    /// Where the friend list gets its updates, the selected conversation is passed along so it stays highlighted.
+    pub fn event_url(&self) -> String {
+        match &self.selected {
+            Some(selected) => format!("/api/chat/event?selected={}", percent_encode(selected)),
+            None => String::from("/api/chat/event"),
+        }
+    }
+
+    pub async fn new(
+        user_id: i32,
+        selected: Option<String>,
+        pool: &PgPool,
+    ) -> anyhow::Result<Self> {
+        let (archived, friends) = get_friends(user_id, pool)
+            .await?
+            .into_iter()
+            .partition(|friend| friend.archived);
 
-    friends_vec.append(
-        &mut sqlx::query!(
-            "SELECT sender_id, sent_at FROM chat_messages WHERE recipient_id = $1",
+        let requests = sqlx::query!(
+            "SELECT username, display_name, profile_picture_key FROM friend_requests JOIN users ON users.id = friend_requests.sender_id WHERE recipient_id = $1 ORDER BY sent_at DESC",
             user_id
         )
         .fetch_all(pool)
         .await?
         .into_iter()
-        .map(|rec| (rec.sender_id, rec.sent_at))
-        .collect(),
-    );
-
-    let mut friends_map: HashMap<i32, Vec<time::PrimitiveDateTime>> = HashMap::new();
-
-    for (friend_id, time) in friends_vec {
-        match friends_map.get_mut(&friend_id) {
-            Some(friend) => {
-                friend.push(time);
-            }
-            None => {
-                friends_map.insert(friend_id, vec![time]);
-            }
-        }
-    }
+        .map(|rec| Username::new(rec.username, rec.display_name, rec.profile_picture_key))
+        .collect();
 
-    let mut friends_and_time = friends_map
-        .iter()
-        .map(|(friend_id, times)| {
-            (
-                friend_id,
-                times
-                    .iter()
-                    .max()
-                    .expect("map should contain one or more timestamps at this point"),
-            )
+        Ok(Self {
+            user_id,
+            selected,
+            friends,
+            archived,
+            requests,
         })
-        .collect::<Vec<_>>();
-
-    friends_and_time.sort_by(|a, b| b.1.cmp(a.1));
+    }
+}
 
-    let friends = friends_and_time
-        .into_iter()
-        .map(|(&friend_id, _)| friend_id)
-        .collect();
+/// Everyone the user has a conversation with or is friends with, pinned ones then most recent conversation first.
+pub async fn get_friends(user_id: i32, pool: &PgPool) -> anyhow::Result<Vec<FriendListEntry>> {
+    let friends = sqlx::query!(
+        r#"
+    WITH contacts AS (
+        SELECT recipient_id AS contact_id FROM chat_messages WHERE sender_id = $1
+        UNION
+        SELECT sender_id FROM chat_messages WHERE recipient_id = $1
+        UNION
+        SELECT friend_id FROM friends WHERE user_id = $1
+    )
+    SELECT
+        users.username,
+        users.display_name,
+        users.profile_picture_key,
+        last_message.sender_id AS "last_sender_id?",
+        last_message.preview AS "last_message?",
+        last_message.sent_at AS "last_sent_at?",
+        last_message.kind = 'system' AS "last_system?",
+        NULLIF(LEFT(message_drafts.msg, $2), '') AS "draft?",
+        (
+            SELECT COUNT(*) FROM mentions
+            JOIN chat_messages ON chat_messages.id = mentions.message_id
+            WHERE mentions.user_id = $1 AND mentions.seen = false
+                AND chat_messages.sender_id = contacts.contact_id
+        ) AS "unseen_mentions!",
+        (
+            SELECT COUNT(*) FROM chat_messages
+            WHERE chat_messages.sender_id = contacts.contact_id AND chat_messages.recipient_id = $1
+                AND chat_messages.kind = 'user'
+                AND (conversation_reads.last_read_at IS NULL OR chat_messages.sent_at > conversation_reads.last_read_at)
+        ) AS "unread!",
+        COALESCE(
+            conversation_settings.muted
+                AND (conversation_settings.muted_until IS NULL OR conversation_settings.muted_until > $3),
+            false
+        ) AS "muted!",
+        COALESCE(
+            conversation_settings.archived_at IS NOT NULL AND NOT EXISTS(
+                SELECT 1 FROM chat_messages
+                WHERE ((sender_id = $1 AND recipient_id = contacts.contact_id)
+                        OR (sender_id = contacts.contact_id AND recipient_id = $1))
+                    AND kind = 'user' AND sent_at > conversation_settings.archived_at
+            ),
+            false
+        ) AS "archived!",
+        COALESCE(conversation_settings.pinned, false) AS "pinned!"
+    FROM contacts
+    JOIN users ON users.id = contacts.contact_id
+    LEFT JOIN conversation_reads
+        ON conversation_reads.user_id = $1 AND conversation_reads.other_user_id = contacts.contact_id
+    LEFT JOIN conversation_settings
+        ON conversation_settings.user_id = $1 AND conversation_settings.other_user_id = contacts.contact_id
+    LEFT JOIN message_drafts
+        ON message_drafts.user_id = $1 AND message_drafts.other_user_id = contacts.contact_id
+    LEFT JOIN LATERAL (
+        SELECT sender_id, LEFT(msg, $2) AS preview, sent_at, kind FROM (
+            (SELECT sender_id, msg, sent_at, kind FROM chat_messages
+                WHERE sender_id = $1 AND recipient_id = contacts.contact_id
+                ORDER BY sent_at DESC LIMIT 1)
+            UNION ALL
+            (SELECT sender_id, msg, sent_at, kind FROM chat_messages
+                WHERE sender_id = contacts.contact_id AND recipient_id = $1
+                ORDER BY sent_at DESC LIMIT 1)
+        ) latest
+        ORDER BY sent_at DESC
+        LIMIT 1
+    ) last_message ON true
+    WHERE NOT EXISTS(
+        SELECT 1 FROM blocked_users
+        WHERE (user_id = $1 AND blocked_id = contacts.contact_id)
+            OR (user_id = contacts.contact_id AND blocked_id = $1)
+    )
+    ORDER BY "pinned!" DESC, last_message.sent_at DESC NULLS LAST, users.username;"#,
+        user_id,
+        PREVIEW_LENGTH,
+        now()
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|rec| FriendListEntry {
+        name: Username::new(rec.username, rec.display_name, rec.profile_picture_key),
+        last_sender_id: rec.last_sender_id,
+        last_message: rec.last_message,
+        last_sent_at: rec.last_sent_at,
+        last_system: rec.last_system.unwrap_or_default(),
+        draft: rec.draft,
+        unseen_mentions: rec.unseen_mentions,
+        unread: rec.unread,
+        muted: rec.muted,
+        archived: rec.archived,
+        pinned: rec.pinned,
+    })
+    .collect();
 
     Ok(friends)
 }
+
+const PREVIEW_LENGTH: i32 = 100;
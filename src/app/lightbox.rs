@@ -0,0 +1,12 @@
+use askama::Template;
+use axum::extract::Path;
+
+pub async fn lightbox(Path(attachment_id): Path<i32>) -> LightboxTemplate {
+    LightboxTemplate { attachment_id }
+}
+
+#[derive(Template)]
+#[template(path = "components/lightbox.html")]
+pub struct LightboxTemplate {
+    pub attachment_id: i32,
+}
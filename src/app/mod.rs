@@ -2,13 +2,22 @@ use askama::Template;
 use http::StatusCode;
 use sqlx::PgPool;
 
-use crate::{api::chat::ChatWindowInfo, data::app_state::AppState, utils::ToServerError};
+use crate::{
+    api::chat::ChatWindowInfo,
+    data::app_state::AppState,
+    notifications::unread_count,
+    profile_pictures::profile_picture_url,
+    utils::{relationship::Relationship, ToServerError},
+};
 
 use self::friend_list::FiendListInfo;
 
-mod friend_list;
-pub mod find_friend;
 pub mod account;
+pub mod find_friend;
+pub mod friend_list;
+pub mod lightbox;
+pub mod search;
+pub mod starred;
 
 pub async fn main(
     state: AppState,
@@ -17,7 +26,7 @@ pub async fn main(
 ) -> Result<Base, (StatusCode, String)> {
     let base_info = BaseInfo::new(user_id, &state.pool).await.server_error()?;
 
-    let chat_window_info = match recipient {
+    let chat_window_info = match recipient.clone() {
         Some(recipient) => {
             let other_user_id = sqlx::query!("SELECT id FROM users WHERE username = $1", recipient)
                 .fetch_one(&state.pool)
@@ -34,7 +43,7 @@ pub async fn main(
         None => None,
     };
 
-    let friend_list_info = FiendListInfo::new(user_id, &state.pool)
+    let friend_list_info = FiendListInfo::new(user_id, recipient, &state.pool)
         .await
         .server_error()?;
 
@@ -59,12 +68,20 @@ pub struct BaseInfo {
     pub user_id: i32,
     pub username: String,
     pub display_name: String,
+    pub profile_picture_key: Option<String>,
+    pub unread_notifications: i64,
 }
 
 impl BaseInfo {
+    pub fn profile_picture(&self, size: u32) -> String {
+        profile_picture_url(&self.username, self.profile_picture_key.as_deref(), size)
+    }
+
     pub async fn new(user_id: i32, pool: &PgPool) -> anyhow::Result<Self> {
+        let unread_notifications = unread_count(user_id, pool).await?;
+
         Ok(sqlx::query!(
-            "SELECT username, display_name FROM users WHERE id = $1",
+            "SELECT username, display_name, profile_picture_key FROM users WHERE id = $1",
             user_id
         )
         .fetch_one(pool)
@@ -73,6 +90,8 @@ impl BaseInfo {
             user_id,
             username: rec.username.clone(),
             display_name: rec.display_name.unwrap_or(rec.username),
+            profile_picture_key: rec.profile_picture_key,
+            unread_notifications,
         })?)
     }
 }
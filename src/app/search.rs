@@ -0,0 +1,160 @@
+use askama::Template;
+use axum::{extract::State, Form};
+use http::StatusCode;
+use time::{macros::format_description, Date, Duration, PrimitiveDateTime, Time};
+
+use crate::{
+    data::app_state::AppState,
+    utils::{auth_layer::ExtractActivatedAuth, username::Username, ToServerError},
+};
+
+const RESULT_LIMIT: i64 = 50;
+
+// ts_headline wraps matches in these so the snippet can be escaped before highlighting
+const HIGHLIGHT_START: char = '\u{2}';
+const HIGHLIGHT_STOP: char = '\u{3}';
+
+pub async fn search_modal() -> SearchModalTemplate {
+    SearchModalTemplate
+}
+
+#[derive(Template)]
+#[template(path = "components/search_modal.html")]
+pub struct SearchModalTemplate;
+
+#[derive(serde::Deserialize)]
+pub struct SearchForm {
+    query: String,
+    sender: Option<String>,
+    conversation: Option<String>,
+    after: Option<String>,
+    before: Option<String>,
+}
+
+pub struct SearchResult {
+    pub id: i32,
+    pub sender: Username,
+    pub conversation: Username,
+    pub sent_at: PrimitiveDateTime,
+    pub snippet: Vec<(String, bool)>,
+}
+
+pub async fn search_list(
+    State(state): State<AppState>,
+    ExtractActivatedAuth(user_id): ExtractActivatedAuth,
+    Form(form): Form<SearchForm>,
+) -> Result<SearchListTemplate, (StatusCode, String)> {
+    let query = form.query.trim();
+
+    if query.is_empty() {
+        return Ok(SearchListTemplate { results: vec![] });
+    }
+
+    let sender = non_empty(form.sender);
+    let conversation = non_empty(form.conversation);
+    let after = parse_date(form.after)?.map(|date| PrimitiveDateTime::new(date, Time::MIDNIGHT));
+    let before = parse_date(form.before)?
+        .map(|date| PrimitiveDateTime::new(date, Time::MIDNIGHT) + Duration::days(1));
+
+    tracing::debug!("message search from user({user_id}) with: {query}");
+
+    let headline_options = format!(
+        "StartSel={HIGHLIGHT_START}, StopSel={HIGHLIGHT_STOP}, MaxWords=20, MinWords=5, MaxFragments=2"
+    );
+
+    let results = sqlx::query!(
+        r#"
+    SELECT
+        chat_messages.id,
+        chat_messages.sent_at,
+        ts_headline('simple', chat_messages.msg, search_query, $7) AS "snippet!",
+        sender.username AS sender_username,
+        sender.display_name AS sender_display_name,
+        sender.profile_picture_key AS sender_profile_picture_key,
+        other.username AS other_username,
+        other.display_name AS other_display_name,
+        other.profile_picture_key AS other_profile_picture_key
+    FROM chat_messages
+    CROSS JOIN websearch_to_tsquery('simple', $2) AS search_query
+    JOIN users sender ON sender.id = chat_messages.sender_id
+    JOIN users other ON other.id = CASE
+        WHEN chat_messages.sender_id = $1 THEN chat_messages.recipient_id
+        ELSE chat_messages.sender_id
+    END
+    WHERE (chat_messages.sender_id = $1 OR chat_messages.recipient_id = $1)
+        AND chat_messages.kind = 'user'
+        AND chat_messages.msg_search @@ search_query
+        AND ($3::TEXT IS NULL OR sender.username = $3)
+        AND ($4::TEXT IS NULL OR other.username = $4)
+        AND ($5::TIMESTAMP IS NULL OR chat_messages.sent_at >= $5)
+        AND ($6::TIMESTAMP IS NULL OR chat_messages.sent_at < $6)
+    ORDER BY ts_rank(chat_messages.msg_search, search_query) DESC, chat_messages.sent_at DESC
+    LIMIT $8;"#,
+        user_id,
+        query,
+        sender,
+        conversation,
+        after,
+        before,
+        headline_options,
+        RESULT_LIMIT
+    )
+    .fetch_all(&state.pool)
+    .await
+    .server_error()?
+    .into_iter()
+    .map(|rec| SearchResult {
+        id: rec.id,
+        sender: Username::new(
+            rec.sender_username,
+            rec.sender_display_name,
+            rec.sender_profile_picture_key,
+        ),
+        conversation: Username::new(
+            rec.other_username,
+            rec.other_display_name,
+            rec.other_profile_picture_key,
+        ),
+        sent_at: rec.sent_at,
+        snippet: split_highlights(&rec.snippet),
+    })
+    .collect();
+
+    Ok(SearchListTemplate { results })
+}
+
+#[derive(Template)]
+#[template(path = "components/search_list.html")]
+pub struct SearchListTemplate {
+    pub results: Vec<SearchResult>,
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_owned())
+        .filter(|value| !value.is_empty())
+}
+
+fn parse_date(value: Option<String>) -> Result<Option<Date>, (StatusCode, String)> {
+    match non_empty(value) {
+        Some(value) => Date::parse(&value, format_description!("[year]-[month]-[day]"))
+            .map(Some)
+            .map_err(|_| (StatusCode::BAD_REQUEST, String::from("Bad Request"))),
+        None => Ok(None),
+    }
+}
+
+/// Splits a ts_headline snippet into (text, highlighted) parts.
+fn split_highlights(snippet: &str) -> Vec<(String, bool)> {
+    let mut parts = vec![];
+    let mut highlighted = false;
+
+    for part in snippet.split([HIGHLIGHT_START, HIGHLIGHT_STOP]) {
+        if !part.is_empty() {
+            parts.push((part.to_owned(), highlighted));
+        }
+        highlighted = !highlighted;
+    }
+
+    parts
+}
@@ -0,0 +1,86 @@
+use askama::Template;
+use axum::{extract::State, response::Redirect};
+use http::StatusCode;
+use time::PrimitiveDateTime;
+
+use crate::{
+    data::app_state::AppState,
+    utils::{
+        auth_layer::ExtractOptionalActivatedAuth, markdown::render_markdown,
+        relative_time::relative_time, username::Username, ToServerError,
+    },
+};
+
+#[derive(Template)]
+#[template(path = "starred.html")]
+pub struct StarredTemplate {
+    messages: Vec<StarredMessage>,
+}
+
+struct StarredMessage {
+    id: i32,
+    sender: Username,
+    /// The other person in the conversation, the link goes to the chat with them.
+    other_user: Username,
+    msg_html: String,
+    has_text: bool,
+    attachments: i64,
+    sent_at: PrimitiveDateTime,
+}
+
+impl StarredMessage {
+    fn sent_ago(&self) -> String {
+        relative_time(self.sent_at)
+    }
+}
+
+/// The messages the user starred, newest star first.
+pub async fn starred_route(
+    State(state): State<AppState>,
+    ExtractOptionalActivatedAuth(user_id): ExtractOptionalActivatedAuth,
+) -> Result<Result<StarredTemplate, Redirect>, (StatusCode, String)> {
+    let Some(user_id) = user_id else {
+        return Ok(Err(Redirect::to("/")));
+    };
+
+    let messages = sqlx::query!(
+        r#"SELECT chat_messages.id, msg, msg_html, sent_at,
+            sender.username AS sender_username, sender.display_name AS sender_display_name, sender.profile_picture_key AS sender_profile_picture_key,
+            other.username AS other_username, other.display_name AS other_display_name, other.profile_picture_key AS other_profile_picture_key,
+            (SELECT COUNT(*) FROM chat_attachments WHERE message_id = chat_messages.id) AS "attachments!"
+        FROM starred_messages
+        JOIN chat_messages ON chat_messages.id = starred_messages.message_id
+        JOIN users sender ON sender.id = chat_messages.sender_id
+        JOIN users other ON other.id = CASE WHEN chat_messages.sender_id = $1 THEN chat_messages.recipient_id ELSE chat_messages.sender_id END
+        WHERE starred_messages.user_id = $1 AND (chat_messages.sender_id = $1 OR chat_messages.recipient_id = $1)
+        ORDER BY starred_at DESC"#,
+        user_id
+    )
+    .fetch_all(&state.pool)
+    .await
+    .server_error()?
+    .into_iter()
+    .map(|rec| StarredMessage {
+        id: rec.id,
+        sender: Username::new(
+            rec.sender_username,
+            rec.sender_display_name,
+            rec.sender_profile_picture_key,
+        ),
+        other_user: Username::new(
+            rec.other_username,
+            rec.other_display_name,
+            rec.other_profile_picture_key,
+        ),
+        // starring needs the chat window so this is cached already, unless a mention was dropped since
+        msg_html: rec
+            .msg_html
+            .unwrap_or_else(|| render_markdown(&rec.msg, &[])),
+        has_text: !rec.msg.is_empty(),
+        attachments: rec.attachments,
+        sent_at: rec.sent_at,
+    })
+    .collect();
+
+    Ok(Ok(StarredTemplate { messages }))
+}
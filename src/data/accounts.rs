@@ -0,0 +1,39 @@
+use sqlx::PgPool;
+
+pub async fn init_account_tables(pool: &PgPool) -> anyhow::Result<()> {
+    sqlx::query!(
+        "
+    CREATE TABLE IF NOT EXISTS account_deletions (
+        user_id INT PRIMARY KEY,
+        requested_at TIMESTAMP NOT NULL,
+        delete_after TIMESTAMP NOT NULL,
+        FOREIGN KEY (user_id) REFERENCES users (id)
+    );"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!(
+        "
+    CREATE TABLE IF NOT EXISTS data_exports (
+        id SERIAL PRIMARY KEY,
+        user_id INT NOT NULL,
+        requested_at TIMESTAMP NOT NULL,
+        finished_at TIMESTAMP,
+        storage_key TEXT,
+        failed BOOLEAN NOT NULL DEFAULT false,
+        FOREIGN KEY (user_id) REFERENCES users (id)
+    );"
+    )
+    .execute(pool)
+    .await?;
+    // at most one export is being built per user
+    sqlx::query!(
+        "
+    CREATE UNIQUE INDEX IF NOT EXISTS data_exports_pending_idx
+        ON data_exports (user_id) WHERE finished_at IS NULL AND NOT failed;"
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
@@ -2,15 +2,26 @@ use std::sync::Arc;
 
 use lettre::SmtpTransport;
 use sqlx::PgPool;
-use tokio::sync::watch;
+use tokio::sync::{broadcast, watch};
 use tower_cookies::Key;
 
+use crate::{
+    notifications::{push::WebPush, OnlineUsers},
+    storage::Storage,
+};
+
 pub struct AppStateInner {
     pub pool: PgPool,
     pub jws_key: String,
     pub cookie_key: Key,
     pub message_sent: watch::Sender<(i32, i32)>,
+    pub notification_sent: watch::Sender<i32>,
+    /// Conversations that lost messages, a broadcast so a batch of deletions isn't collapsed into one.
+    pub messages_deleted: broadcast::Sender<(i32, i32)>,
+    pub online_users: OnlineUsers,
+    pub web_push: Option<WebPush>,
     pub mailer: SmtpTransport,
+    pub storage: Storage,
 }
 
 pub type AppState = Arc<AppStateInner>;
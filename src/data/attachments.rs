@@ -0,0 +1,40 @@
+use sqlx::PgPool;
+
+pub async fn init_attachments_table(pool: &PgPool) -> anyhow::Result<()> {
+    sqlx::query!(
+        "
+    CREATE TABLE IF NOT EXISTS chat_attachments (
+        id SERIAL PRIMARY KEY,
+        message_id INT NOT NULL,
+        file_name TEXT NOT NULL,
+        content_type TEXT NOT NULL,
+        size INT NOT NULL,
+        storage_key TEXT NOT NULL,
+        thumbnail_key TEXT,
+        width INT,
+        height INT,
+        FOREIGN KEY (message_id) REFERENCES chat_messages (id)
+    );"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!(
+        "
+    ALTER TABLE chat_attachments
+        ADD COLUMN IF NOT EXISTS storage_key TEXT,
+        ADD COLUMN IF NOT EXISTS thumbnail_key TEXT,
+        ADD COLUMN IF NOT EXISTS width INT,
+        ADD COLUMN IF NOT EXISTS height INT;"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!(
+        "
+    CREATE INDEX IF NOT EXISTS chat_attachments_message_idx
+        ON chat_attachments (message_id);"
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
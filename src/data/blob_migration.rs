@@ -0,0 +1,131 @@
+use sqlx::{PgPool, Row};
+
+use crate::storage::{self, BlobStore};
+
+// these columns no longer exist in the schema so the queries can't be checked at compile time
+
+/// Rows moved per transaction, the blobs of a batch are all in memory at once.
+const BATCH_SIZE: i64 = 50;
+
+/// Moves profile pictures and attachments that were stored as `BYTEA` columns into blob
+/// storage and drops the old columns. Does nothing once the columns are gone.
+pub async fn move_blobs_to_storage(pool: &PgPool, storage: &dyn BlobStore) -> anyhow::Result<()> {
+    if column_exists(pool, "users", "profile_picture").await? {
+        tracing::info!("moving profile pictures to storage");
+
+        let mut last_id = 0;
+
+        loop {
+            let mut transaction = pool.begin().await?;
+
+            let recs = sqlx::query(
+                "SELECT id, profile_picture FROM users WHERE profile_picture IS NOT NULL AND id > $1 ORDER BY id LIMIT $2",
+            )
+            .bind(last_id)
+            .bind(BATCH_SIZE)
+            .fetch_all(&mut *transaction)
+            .await?;
+
+            if recs.is_empty() {
+                break;
+            }
+
+            for rec in &recs {
+                let id: i32 = rec.try_get("id")?;
+                let picture: Vec<u8> = rec.try_get("profile_picture")?;
+
+                let key = storage::store(storage, &picture, &mut transaction).await?;
+
+                sqlx::query!(
+                    "UPDATE users SET profile_picture_key = $1 WHERE id = $2",
+                    key,
+                    id
+                )
+                .execute(&mut *transaction)
+                .await?;
+
+                last_id = id;
+            }
+
+            transaction.commit().await?;
+
+            tracing::info!("moved {} profile pictures to storage", recs.len());
+        }
+
+        sqlx::query("ALTER TABLE users DROP COLUMN profile_picture")
+            .execute(pool)
+            .await?;
+    }
+
+    if column_exists(pool, "chat_attachments", "data").await? {
+        tracing::info!("moving attachments to storage");
+
+        let mut last_id = 0;
+
+        loop {
+            let mut transaction = pool.begin().await?;
+
+            let recs = sqlx::query(
+                "SELECT id, data, thumbnail FROM chat_attachments WHERE id > $1 ORDER BY id LIMIT $2",
+            )
+            .bind(last_id)
+            .bind(BATCH_SIZE)
+            .fetch_all(&mut *transaction)
+            .await?;
+
+            if recs.is_empty() {
+                break;
+            }
+
+            for rec in &recs {
+                let id: i32 = rec.try_get("id")?;
+                let data: Vec<u8> = rec.try_get("data")?;
+                let thumbnail: Option<Vec<u8>> = rec.try_get("thumbnail")?;
+
+                let storage_key = storage::store(storage, &data, &mut transaction).await?;
+                let thumbnail_key = match thumbnail {
+                    Some(thumbnail) => {
+                        Some(storage::store(storage, &thumbnail, &mut transaction).await?)
+                    }
+                    None => None,
+                };
+
+                sqlx::query!(
+                    "UPDATE chat_attachments SET storage_key = $1, thumbnail_key = $2 WHERE id = $3",
+                    storage_key,
+                    thumbnail_key,
+                    id
+                )
+                .execute(&mut *transaction)
+                .await?;
+
+                last_id = id;
+            }
+
+            transaction.commit().await?;
+
+            tracing::info!("moved {} attachments to storage", recs.len());
+        }
+
+        sqlx::query(
+            "ALTER TABLE chat_attachments
+                DROP COLUMN data,
+                DROP COLUMN IF EXISTS thumbnail,
+                ALTER COLUMN storage_key SET NOT NULL",
+        )
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+async fn column_exists(pool: &PgPool, table: &str, column: &str) -> anyhow::Result<bool> {
+    Ok(sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM information_schema.columns WHERE table_name = $1 AND column_name = $2)",
+    )
+    .bind(table)
+    .bind(column)
+    .fetch_one(pool)
+    .await?)
+}
@@ -15,6 +15,176 @@ pub async fn init_chat_table(pool: &PgPool) -> anyhow::Result<()> {
     )
     .execute(pool)
     .await?;
+    sqlx::query!(
+        "
+    CREATE INDEX IF NOT EXISTS chat_messages_sender_recipient_idx
+        ON chat_messages (sender_id, recipient_id, sent_at DESC);"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!(
+        "
+    CREATE INDEX IF NOT EXISTS chat_messages_recipient_sender_idx
+        ON chat_messages (recipient_id, sender_id, sent_at DESC);"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!(
+        "
+    ALTER TABLE chat_messages ADD COLUMN IF NOT EXISTS msg_search TSVECTOR
+        GENERATED ALWAYS AS (to_tsvector('simple', msg)) STORED;"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!(
+        "
+    ALTER TABLE chat_messages ADD COLUMN IF NOT EXISTS msg_html TEXT;"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!(
+        "
+    ALTER TABLE chat_messages ADD COLUMN IF NOT EXISTS expires_at TIMESTAMP;"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!(
+        "
+    ALTER TABLE chat_messages ADD COLUMN IF NOT EXISTS kind TEXT NOT NULL DEFAULT 'user'
+        CHECK (kind IN ('user', 'system'));"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!(
+        "
+    CREATE INDEX IF NOT EXISTS chat_messages_expires_at_idx
+        ON chat_messages (expires_at) WHERE expires_at IS NOT NULL;"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!(
+        "
+    CREATE INDEX IF NOT EXISTS chat_messages_msg_search_idx
+        ON chat_messages USING GIN (msg_search);"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query!(
+        "
+    CREATE TABLE IF NOT EXISTS conversation_reads (
+        user_id INT NOT NULL,
+        other_user_id INT NOT NULL,
+        last_read_at TIMESTAMP NOT NULL,
+        PRIMARY KEY (user_id, other_user_id),
+        FOREIGN KEY (user_id) REFERENCES users (id),
+        FOREIGN KEY (other_user_id) REFERENCES users (id)
+    );"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query!(
+        "
+    CREATE TABLE IF NOT EXISTS conversation_settings (
+        user_id INT NOT NULL,
+        other_user_id INT NOT NULL,
+        muted BOOLEAN NOT NULL DEFAULT false,
+        muted_until TIMESTAMP,
+        archived_at TIMESTAMP,
+        pinned BOOLEAN NOT NULL DEFAULT false,
+        PRIMARY KEY (user_id, other_user_id),
+        FOREIGN KEY (user_id) REFERENCES users (id),
+        FOREIGN KEY (other_user_id) REFERENCES users (id)
+    );"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query!(
+        "
+    CREATE TABLE IF NOT EXISTS pinned_messages (
+        message_id INT PRIMARY KEY,
+        pinned_by INT NOT NULL,
+        pinned_at TIMESTAMP NOT NULL,
+        FOREIGN KEY (message_id) REFERENCES chat_messages (id),
+        FOREIGN KEY (pinned_by) REFERENCES users (id)
+    );"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query!(
+        "
+    CREATE TABLE IF NOT EXISTS starred_messages (
+        user_id INT NOT NULL,
+        message_id INT NOT NULL,
+        starred_at TIMESTAMP NOT NULL,
+        PRIMARY KEY (user_id, message_id),
+        FOREIGN KEY (user_id) REFERENCES users (id),
+        FOREIGN KEY (message_id) REFERENCES chat_messages (id)
+    );"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query!(
+        "
+    CREATE TABLE IF NOT EXISTS scheduled_messages (
+        id SERIAL PRIMARY KEY,
+        sender_id INT NOT NULL,
+        recipient_id INT NOT NULL,
+        msg TEXT NOT NULL,
+        send_at TIMESTAMP NOT NULL,
+        created_at TIMESTAMP NOT NULL,
+        failed BOOLEAN NOT NULL DEFAULT false,
+        FOREIGN KEY (sender_id) REFERENCES users (id),
+        FOREIGN KEY (recipient_id) REFERENCES users (id)
+    );"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!(
+        "
+    CREATE INDEX IF NOT EXISTS scheduled_messages_due_idx
+        ON scheduled_messages (send_at) WHERE failed = false;"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query!(
+        "
+    CREATE TABLE IF NOT EXISTS disappearing_messages (
+        user_a INT NOT NULL,
+        user_b INT NOT NULL,
+        period TEXT NOT NULL,
+        changed_by INT NOT NULL,
+        changed_at TIMESTAMP NOT NULL,
+        PRIMARY KEY (user_a, user_b),
+        CHECK (user_a < user_b),
+        FOREIGN KEY (user_a) REFERENCES users (id),
+        FOREIGN KEY (user_b) REFERENCES users (id),
+        FOREIGN KEY (changed_by) REFERENCES users (id)
+    );"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query!(
+        "
+    CREATE TABLE IF NOT EXISTS message_drafts (
+        user_id INT NOT NULL,
+        other_user_id INT NOT NULL,
+        msg TEXT NOT NULL,
+        updated_at TIMESTAMP NOT NULL,
+        last_sent_id INT NOT NULL DEFAULT 0,
+        PRIMARY KEY (user_id, other_user_id),
+        FOREIGN KEY (user_id) REFERENCES users (id),
+        FOREIGN KEY (other_user_id) REFERENCES users (id)
+    );"
+    )
+    .execute(pool)
+    .await?;
 
     Ok(())
 }
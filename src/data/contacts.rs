@@ -0,0 +1,45 @@
+use sqlx::PgPool;
+
+pub async fn init_contacts_tables(pool: &PgPool) -> anyhow::Result<()> {
+    sqlx::query!(
+        "
+    CREATE TABLE IF NOT EXISTS friend_requests (
+        sender_id INT NOT NULL,
+        recipient_id INT NOT NULL,
+        sent_at TIMESTAMP NOT NULL,
+        PRIMARY KEY (sender_id, recipient_id),
+        FOREIGN KEY (sender_id) REFERENCES users (id),
+        FOREIGN KEY (recipient_id) REFERENCES users (id)
+    );"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!(
+        "
+    CREATE TABLE IF NOT EXISTS friends (
+        user_id INT NOT NULL,
+        friend_id INT NOT NULL,
+        since TIMESTAMP NOT NULL,
+        PRIMARY KEY (user_id, friend_id),
+        FOREIGN KEY (user_id) REFERENCES users (id),
+        FOREIGN KEY (friend_id) REFERENCES users (id)
+    );"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!(
+        "
+    CREATE TABLE IF NOT EXISTS blocked_users (
+        user_id INT NOT NULL,
+        blocked_id INT NOT NULL,
+        blocked_at TIMESTAMP NOT NULL,
+        PRIMARY KEY (user_id, blocked_id),
+        FOREIGN KEY (user_id) REFERENCES users (id),
+        FOREIGN KEY (blocked_id) REFERENCES users (id)
+    );"
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use sqlx::PgPool;
+use time::PrimitiveDateTime;
+
+use crate::{tasks::now, utils::relative_time::full_date};
+
+/// How long a conversation stays muted.
+#[derive(Clone, Copy, PartialEq)]
+pub enum MuteDuration {
+    Hour,
+    EightHours,
+    Day,
+    Week,
+    Forever,
+}
+
+impl MuteDuration {
+    pub const ALL: [MuteDuration; 5] = [
+        MuteDuration::Hour,
+        MuteDuration::EightHours,
+        MuteDuration::Day,
+        MuteDuration::Week,
+        MuteDuration::Forever,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MuteDuration::Hour => "hour",
+            MuteDuration::EightHours => "eight_hours",
+            MuteDuration::Day => "day",
+            MuteDuration::Week => "week",
+            MuteDuration::Forever => "forever",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|duration| duration.name() == name)
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            MuteDuration::Hour => "For 1 hour",
+            MuteDuration::EightHours => "For 8 hours",
+            MuteDuration::Day => "For 1 day",
+            MuteDuration::Week => "For 1 week",
+            MuteDuration::Forever => "Until I unmute it",
+        }
+    }
+
+    pub fn duration(&self) -> Option<time::Duration> {
+        match self {
+            MuteDuration::Hour => Some(time::Duration::hours(1)),
+            MuteDuration::EightHours => Some(time::Duration::hours(8)),
+            MuteDuration::Day => Some(time::Duration::days(1)),
+            MuteDuration::Week => Some(time::Duration::weeks(1)),
+            MuteDuration::Forever => None,
+        }
+    }
+}
+
+/// What the user set for their side of a conversation, the other person has their own.
+#[derive(Default)]
+pub struct ConversationSettings {
+    pub muted: bool,
+    /// Only set while muted for a limited time.
+    pub muted_until: Option<PrimitiveDateTime>,
+    /// Archived conversations come back once a new message is sent in them.
+    pub archived: bool,
+    pub pinned: bool,
+}
+
+impl ConversationSettings {
+    pub async fn new(user_id: i32, other_user_id: i32, pool: &PgPool) -> anyhow::Result<Self> {
+        Ok(sqlx::query!(
+            r#"SELECT
+                muted AND (muted_until IS NULL OR muted_until > $3) AS "muted!",
+                muted_until,
+                archived_at IS NOT NULL AND NOT EXISTS(
+                    SELECT 1 FROM chat_messages
+                    WHERE ((sender_id = $1 AND recipient_id = $2) OR (sender_id = $2 AND recipient_id = $1))
+                        AND kind = 'user' AND sent_at > archived_at
+                ) AS "archived!",
+                pinned
+            FROM conversation_settings WHERE user_id = $1 AND other_user_id = $2"#,
+            user_id,
+            other_user_id,
+            now()
+        )
+        .fetch_optional(pool)
+        .await?
+        .map(|rec| Self {
+            muted: rec.muted,
+            muted_until: rec.muted_until.filter(|_| rec.muted),
+            archived: rec.archived,
+            pinned: rec.pinned,
+        })
+        .unwrap_or_default())
+    }
+
+    pub fn muted_until_text(&self) -> Option<String> {
+        self.muted_until.map(|muted_until| {
+            format!(
+                "{} {:02}:{:02}",
+                full_date(muted_until),
+                muted_until.hour(),
+                muted_until.minute()
+            )
+        })
+    }
+
+    pub fn mute_durations(&self) -> [MuteDuration; 5] {
+        MuteDuration::ALL
+    }
+}
+
+/// Muted conversations don't notify the user in any way.
+pub async fn is_muted(user_id: i32, other_user_id: i32, pool: &PgPool) -> anyhow::Result<bool> {
+    Ok(ConversationSettings::new(user_id, other_user_id, pool)
+        .await?
+        .muted)
+}
@@ -0,0 +1,26 @@
+use sqlx::PgPool;
+
+pub async fn init_mentions_table(pool: &PgPool) -> anyhow::Result<()> {
+    sqlx::query!(
+        "
+    CREATE TABLE IF NOT EXISTS mentions (
+        message_id INT NOT NULL,
+        user_id INT NOT NULL,
+        seen BOOLEAN NOT NULL DEFAULT false,
+        PRIMARY KEY (message_id, user_id),
+        FOREIGN KEY (message_id) REFERENCES chat_messages (id),
+        FOREIGN KEY (user_id) REFERENCES users (id)
+    );"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!(
+        "
+    CREATE INDEX IF NOT EXISTS mentions_user_unseen_idx
+        ON mentions (user_id) WHERE seen = false;"
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
@@ -1,11 +1,23 @@
+mod accounts;
+mod activated;
 pub mod app_state;
+mod attachments;
+pub mod blob_migration;
 mod chat;
+mod contacts;
+pub mod conversation_settings;
+mod mentions;
+mod notifications;
 mod users;
-mod activated;
 
 use sqlx::PgPool;
 
-use self::{chat::init_chat_table, users::init_user_tables, activated::init_activations_table};
+use self::{
+    accounts::init_account_tables, activated::init_activations_table,
+    attachments::init_attachments_table, chat::init_chat_table, contacts::init_contacts_tables,
+    mentions::init_mentions_table, notifications::init_notification_tables,
+    users::init_user_tables,
+};
 
 pub async fn database_init() -> anyhow::Result<PgPool> {
     let pool = PgPool::connect(&dotenvy::var("DATABASE_URL")?).await?;
@@ -16,5 +28,10 @@ pub async fn init_tables(pool: &PgPool) -> anyhow::Result<()> {
     init_user_tables(pool).await?;
     init_chat_table(pool).await?;
     init_activations_table(pool).await?;
+    init_contacts_tables(pool).await?;
+    init_attachments_table(pool).await?;
+    init_account_tables(pool).await?;
+    init_mentions_table(pool).await?;
+    init_notification_tables(pool).await?;
     Ok(())
 }
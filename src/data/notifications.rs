@@ -0,0 +1,73 @@
+use sqlx::PgPool;
+
+pub async fn init_notification_tables(pool: &PgPool) -> anyhow::Result<()> {
+    sqlx::query!(
+        "
+    CREATE TABLE IF NOT EXISTS notifications (
+        id SERIAL PRIMARY KEY,
+        user_id INT NOT NULL,
+        kind TEXT NOT NULL,
+        actor_id INT,
+        message_id INT,
+        detail TEXT,
+        created_at TIMESTAMP NOT NULL,
+        read_at TIMESTAMP,
+        FOREIGN KEY (user_id) REFERENCES users (id),
+        FOREIGN KEY (actor_id) REFERENCES users (id),
+        FOREIGN KEY (message_id) REFERENCES chat_messages (id)
+    );"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!(
+        "
+    CREATE INDEX IF NOT EXISTS notifications_user_idx
+        ON notifications (user_id, created_at DESC);"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!(
+        "
+    CREATE TABLE IF NOT EXISTS notification_preferences (
+        user_id INT NOT NULL,
+        kind TEXT NOT NULL,
+        enabled BOOLEAN NOT NULL,
+        PRIMARY KEY (user_id, kind),
+        FOREIGN KEY (user_id) REFERENCES users (id)
+    );"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query!(
+        "
+    CREATE TABLE IF NOT EXISTS push_subscriptions (
+        id SERIAL PRIMARY KEY,
+        user_id INT NOT NULL,
+        endpoint TEXT NOT NULL UNIQUE,
+        p256dh TEXT NOT NULL,
+        auth TEXT NOT NULL,
+        created_at TIMESTAMP NOT NULL,
+        last_used_at TIMESTAMP,
+        FOREIGN KEY (user_id) REFERENCES users (id)
+    );"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query!(
+        "
+    CREATE TABLE IF NOT EXISTS email_digests (
+        user_id INT PRIMARY KEY,
+        frequency TEXT NOT NULL,
+        covered_until TIMESTAMP NOT NULL,
+        next_digest_at TIMESTAMP NOT NULL,
+        last_sent_at TIMESTAMP,
+        FOREIGN KEY (user_id) REFERENCES users (id)
+    );"
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
@@ -7,7 +7,7 @@ pub async fn init_user_tables(pool: &PgPool) -> anyhow::Result<()> {
         id SERIAL PRIMARY KEY,
         username TEXT UNIQUE NOT NULL,
         display_name TEXT,
-        profile_picture BYTEA,
+        profile_picture_key TEXT,
         email TEXT UNIQUE NOT NULL,
         password_hash TEXT NOT NULL,
         activated BOOLEAN
@@ -15,6 +15,50 @@ pub async fn init_user_tables(pool: &PgPool) -> anyhow::Result<()> {
     )
     .execute(pool)
     .await?;
+    sqlx::query!(
+        "
+    ALTER TABLE users
+        ADD COLUMN IF NOT EXISTS friends_only_messages BOOLEAN NOT NULL DEFAULT false,
+        ADD COLUMN IF NOT EXISTS profile_picture_key TEXT,
+        ADD COLUMN IF NOT EXISTS profile_picture_updated_at TIMESTAMP,
+        ADD COLUMN IF NOT EXISTS bio TEXT,
+        ADD COLUMN IF NOT EXISTS status TEXT,
+        ADD COLUMN IF NOT EXISTS friends_only_profile BOOLEAN NOT NULL DEFAULT false,
+        ADD COLUMN IF NOT EXISTS created_at TIMESTAMP,
+        ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMP;"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!("CREATE EXTENSION IF NOT EXISTS pg_trgm;")
+        .execute(pool)
+        .await?;
+    sqlx::query!(
+        "
+    CREATE INDEX IF NOT EXISTS users_username_trgm_idx
+        ON users USING GIN (lower(username) gin_trgm_ops);"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!(
+        "
+    CREATE INDEX IF NOT EXISTS users_display_name_trgm_idx
+        ON users USING GIN (lower(COALESCE(display_name, '')) gin_trgm_ops);"
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query!(
+        "
+    CREATE TABLE IF NOT EXISTS profile_picture_variants (
+        user_id INT NOT NULL,
+        size INT NOT NULL,
+        format TEXT NOT NULL,
+        storage_key TEXT NOT NULL,
+        PRIMARY KEY (user_id, size, format),
+        FOREIGN KEY (user_id) REFERENCES users (id)
+    );"
+    )
+    .execute(pool)
+    .await?;
     sqlx::query!(
         "
     CREATE TABLE IF NOT EXISTS auth_tokens (
@@ -9,23 +9,32 @@ use axum::{
     Router,
 };
 use data::app_state::AppState;
-use http::{header, HeaderMap, HeaderValue, StatusCode};
+use http::{HeaderMap, StatusCode};
 use lettre::{transport::smtp::authentication::Credentials, SmtpTransport};
-use tokio::sync::watch;
+use tokio::sync::{broadcast, watch};
 use tower_cookies::{CookieManagerLayer, Cookies, Key};
 use tower_http::services::ServeDir;
 use tracing_subscriber::prelude::*;
 use utils::{auth_layer::ExtractOptionalAuth, username::Username, ToServerError};
 
 use crate::{
-    app::find_friend::{find_friend_list, find_friend_modal},
-    data::app_state::AppStateInner, activate::activate_routes,
+    activate::activate_routes,
+    app::{
+        find_friend::{find_friend_list, find_friend_modal, mention_suggestions},
+        lightbox::lightbox,
+        search::{search_list, search_modal},
+    },
+    data::app_state::AppStateInner,
 };
 
+mod activate;
 mod api;
 mod app;
 mod data;
-mod activate;
+mod notifications;
+mod profile_pictures;
+mod storage;
+mod tasks;
 mod utils;
 
 #[tokio::main]
@@ -56,7 +65,34 @@ async fn main() {
         return;
     };
 
+    let storage = match storage::storage_init().await {
+        Ok(storage) => storage,
+        Err(error) => {
+            tracing::error!("Failed to initalize storage with error ({error})");
+            return;
+        }
+    };
+
+    if let Err(error) = data::blob_migration::move_blobs_to_storage(&pool, &*storage).await {
+        tracing::error!("Failed to move files out of the database with error ({error})");
+        return;
+    };
+
+    let web_push = match notifications::push::web_push_init() {
+        Ok(web_push) => web_push,
+        Err(error) => {
+            tracing::error!("Failed to initalize web push with error ({error})");
+            return;
+        }
+    };
+
+    if web_push.is_none() {
+        tracing::warn!("VAPID_PRIVATE_KEY is not set, push notifications are turned off");
+    }
+
     let (sender, _) = watch::channel((-1, -1));
+    let (notification_sender, _) = watch::channel(-1);
+    let (messages_deleted_sender, _) = broadcast::channel(64);
 
     let cookie_key_master = match dotenvy::var("COOKIE_KEY") {
         Ok(cookie_key_text) => match hex::decode(cookie_key_text) {
@@ -126,25 +162,45 @@ async fn main() {
         jws_key,
         cookie_key: Key::from(&cookie_key_master),
         message_sent: sender,
+        notification_sent: notification_sender,
+        messages_deleted: messages_deleted_sender,
+        online_users: Default::default(),
+        web_push,
         mailer,
+        storage,
     });
 
+    tasks::spawn_background_tasks(app_state.clone());
+
     let app = Router::new()
         .route("/", get(handler))
         .route("/chat/:recipient", get(handler_chat))
         .route("/login", get(login))
         .route("/signup", get(signup))
         .nest("/api", api::api_routes())
-        .route("/profile_pictures/:username", get(profile_pictures))
+        .nest(
+            "/profile_pictures",
+            profile_pictures::profile_picture_routes(),
+        )
         .nest_service("/assets", ServeDir::new("assets/"))
         .route("/inner/modal/list", post(find_friend_list))
+        .route("/inner/search/list", post(search_list))
+        .route("/inner/mentions/:recipient", get(mention_suggestions))
         .route("/account/:username", get(app::account::account_route))
+        .route("/starred", get(app::starred::starred_route))
         .nest("/confirm", activate_routes())
+        .nest(
+            "/unsubscribe",
+            api::account::email_digest::unsubscribe_routes(),
+        )
         .fallback(not_found)
         .with_state(app_state)
         .layer(CookieManagerLayer::new())
         .route("/inner/empty", get(empty))
-        .route("/inner/modal", get(find_friend_modal));
+        .route("/highlight.css", get(highlight_css))
+        .route("/inner/modal", get(find_friend_modal))
+        .route("/inner/search", get(search_modal))
+        .route("/inner/lightbox/:attachment_id", get(lightbox));
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
     println!("listening on {}", addr);
@@ -285,44 +341,13 @@ async fn not_found(
 #[template(path = "not_found.html")]
 struct NotFoundTemplate;
 
-async fn profile_pictures(
-    Path(username): Path<String>,
-    State(state): State<AppState>,
-) -> Result<Result<impl IntoResponse, Redirect>, (StatusCode, String)> {
-    let picture = sqlx::query!(
-        "SELECT profile_picture FROM users WHERE username = $1",
-        username
-    )
-    .fetch_optional(&state.pool)
-    .await
-    .server_error()?
-    .map(|rec| rec.profile_picture);
-
-    match picture {
-        Some(Some(picture)) => {
-            let body = picture;
-
-            let mut headers = HeaderMap::new();
-
-            headers.append(
-                header::CONTENT_TYPE,
-                HeaderValue::from_static("image/avif;"),
-            );
-            headers.append(
-                header::CONTENT_DISPOSITION,
-                HeaderValue::from_static("attachment;"),
-            );
-
-            Ok(Ok((headers, body)))
-        }
-        Some(None) => Ok(Err(Redirect::to("/assets/default_profile.avif"))),
-        None => Err((
-            StatusCode::NOT_FOUND,
-            String::from("Profile Picture Not Found"),
-        )),
-    }
-}
-
 async fn empty() -> impl IntoResponse {
     StatusCode::OK
 }
+
+async fn highlight_css() -> impl IntoResponse {
+    (
+        [(http::header::CONTENT_TYPE, "text/css; charset=utf-8")],
+        utils::markdown::highlight_css(),
+    )
+}
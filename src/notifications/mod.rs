@@ -0,0 +1,237 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use sqlx::PgPool;
+
+use self::push::{send_push, PushPayload};
+use crate::{
+    data::{app_state::AppState, conversation_settings::is_muted},
+    tasks::now,
+    utils::{percent_encode, username::Username},
+};
+
+pub mod push;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NotificationKind {
+    Message,
+    Mention,
+    FriendRequest,
+    NewLogin,
+}
+
+impl NotificationKind {
+    pub const ALL: [NotificationKind; 4] = [
+        NotificationKind::Message,
+        NotificationKind::Mention,
+        NotificationKind::FriendRequest,
+        NotificationKind::NewLogin,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NotificationKind::Message => "message",
+            NotificationKind::Mention => "mention",
+            NotificationKind::FriendRequest => "friend_request",
+            NotificationKind::NewLogin => "new_login",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    /// Shown next to the checkbox on the account page.
+    pub fn description(&self) -> &'static str {
+        match self {
+            NotificationKind::Message => "New messages while I'm away",
+            NotificationKind::Mention => "Someone mentions me",
+            NotificationKind::FriendRequest => "Friend requests",
+            NotificationKind::NewLogin => "New sign ins to my account",
+        }
+    }
+}
+
+/// Who a notification is about, both are optional depending on the kind.
+#[derive(Default)]
+pub struct NotificationSubject<'a> {
+    pub actor_id: Option<i32>,
+    pub message_id: Option<i32>,
+    pub detail: Option<&'a str>,
+}
+
+/// Stores a notification for the user unless they turned that kind off or muted the conversation, and tells their open pages.
+/// Whatever caused it has already happened so a notification that can't be stored is only logged.
+pub async fn notify(
+    state: &AppState,
+    user_id: i32,
+    kind: NotificationKind,
+    subject: NotificationSubject<'_>,
+) {
+    if let Err(error) = store_notification(state, user_id, kind, subject).await {
+        tracing::error!(
+            "Failed to notify user({user_id}) of {} with error ({error})",
+            kind.name()
+        );
+    }
+}
+
+async fn store_notification(
+    state: &AppState,
+    user_id: i32,
+    kind: NotificationKind,
+    subject: NotificationSubject<'_>,
+) -> anyhow::Result<()> {
+    if !is_enabled(user_id, kind, &state.pool).await? {
+        tracing::debug!("user({user_id}) turned off {} notifications", kind.name());
+        return Ok(());
+    }
+
+    if let (NotificationKind::Message | NotificationKind::Mention, Some(actor_id)) =
+        (kind, subject.actor_id)
+    {
+        if is_muted(user_id, actor_id, &state.pool).await? {
+            tracing::debug!("user({user_id}) muted the conversation with user({actor_id})");
+            return Ok(());
+        }
+    }
+
+    sqlx::query!(
+        "INSERT INTO notifications(user_id, kind, actor_id, message_id, detail, created_at) VALUES ($1, $2, $3, $4, $5, $6)",
+        user_id,
+        kind.name(),
+        subject.actor_id,
+        subject.message_id,
+        subject.detail,
+        now()
+    )
+    .execute(&state.pool)
+    .await?;
+
+    tracing::debug!("{} notification for user({user_id})", kind.name());
+
+    state.notification_sent.send_replace(user_id);
+
+    // users without an open page only find out through a push message
+    if state.web_push.is_some() && !state.online_users.is_online(user_id) {
+        let actor = match subject.actor_id {
+            Some(actor_id) => Some(Username::new_from_id(actor_id, &state.pool).await?),
+            None => None,
+        };
+
+        let payload = PushPayload {
+            title: String::from("Hats Chat"),
+            body: describe(kind, actor.as_ref(), subject.detail),
+            url: link(kind, actor.as_ref(), subject.message_id),
+        };
+
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(error) = send_push(&state, user_id, &payload).await {
+                tracing::error!("Failed to send push to user({user_id}) with error ({error})");
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// The sentence shown for a notification.
+pub fn describe(kind: NotificationKind, actor: Option<&Username>, detail: Option<&str>) -> String {
+    let actor = actor.map_or_else(|| String::from("Someone"), Username::display_name);
+
+    match kind {
+        NotificationKind::Message => format!("{actor} sent you a message"),
+        NotificationKind::Mention => format!("{actor} mentioned you"),
+        NotificationKind::FriendRequest => format!("{actor} sent you a friend request"),
+        NotificationKind::NewLogin => match detail {
+            Some(detail) => format!("New sign in to your account from {detail}"),
+            None => String::from("New sign in to your account"),
+        },
+    }
+}
+
+/// Where opening a notification takes the user.
+pub fn link(kind: NotificationKind, actor: Option<&Username>, message_id: Option<i32>) -> String {
+    match (kind, actor) {
+        (NotificationKind::Message | NotificationKind::Mention, Some(actor)) => match message_id {
+            Some(message_id) => format!(
+                "/chat/{}#message-{message_id}",
+                percent_encode(&actor.username())
+            ),
+            None => format!("/chat/{}", percent_encode(&actor.username())),
+        },
+        (NotificationKind::FriendRequest, Some(actor)) => {
+            format!("/account/{}", percent_encode(&actor.username()))
+        }
+        _ => String::from("/"),
+    }
+}
+
+/// Every kind is on until the user turns it off.
+pub async fn is_enabled(
+    user_id: i32,
+    kind: NotificationKind,
+    pool: &PgPool,
+) -> anyhow::Result<bool> {
+    Ok(sqlx::query!(
+        "SELECT enabled FROM notification_preferences WHERE user_id = $1 AND kind = $2",
+        user_id,
+        kind.name()
+    )
+    .fetch_optional(pool)
+    .await?
+    .is_none_or(|rec| rec.enabled))
+}
+
+pub async fn unread_count(user_id: i32, pool: &PgPool) -> anyhow::Result<i64> {
+    Ok(sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM notifications WHERE user_id = $1 AND read_at IS NULL"#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await?
+    .count)
+}
+
+/// Number of open event streams per user, a user with none is away.
+/// This only counts the streams of this server, with several servers behind a load balancer
+/// a user with a page open on another one looks away here and gets pushes and digests anyway.
+#[derive(Default)]
+pub struct OnlineUsers(Mutex<HashMap<i32, usize>>);
+
+impl OnlineUsers {
+    pub fn is_online(&self, user_id: i32) -> bool {
+        self.0
+            .lock()
+            .is_ok_and(|online| online.get(&user_id).is_some_and(|&count| count > 0))
+    }
+}
+
+/// Keeps the user marked as online for as long as it is alive.
+pub struct OnlineGuard {
+    state: AppState,
+    user_id: i32,
+}
+
+impl OnlineGuard {
+    pub fn new(state: AppState, user_id: i32) -> Self {
+        if let Ok(mut online) = state.online_users.0.lock() {
+            *online.entry(user_id).or_default() += 1;
+        }
+
+        Self { state, user_id }
+    }
+}
+
+impl Drop for OnlineGuard {
+    fn drop(&mut self) {
+        if let Ok(mut online) = self.state.online_users.0.lock() {
+            if let Some(count) = online.get_mut(&self.user_id) {
+                *count -= 1;
+                if *count == 0 {
+                    online.remove(&self.user_id);
+                }
+            }
+        }
+    }
+}
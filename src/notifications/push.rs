@@ -0,0 +1,178 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::Serialize;
+use web_push::{
+    ContentEncoding, HyperWebPushClient, PartialVapidSignatureBuilder, SubscriptionInfo,
+    VapidSignatureBuilder, WebPushClient, WebPushError, WebPushMessageBuilder,
+};
+
+use crate::{data::app_state::AppState, tasks::now};
+
+/// How long the push service keeps a message for a device that is offline.
+const PUSH_TTL_SECONDS: u32 = 24 * 60 * 60;
+
+/// Hosts of the push services browsers subscribe with, a leading dot matches any subdomain.
+/// Endpoints are posted to by the server so anything else could reach internal hosts.
+const PUSH_SERVICE_HOSTS: [&str; 4] = [
+    "fcm.googleapis.com",
+    ".push.services.mozilla.com",
+    ".push.apple.com",
+    ".notify.windows.com",
+];
+
+/// Sends Web Push messages signed with the server's VAPID key.
+pub struct WebPush {
+    client: HyperWebPushClient,
+    signature_builder: PartialVapidSignatureBuilder,
+    subject: String,
+    public_key: String,
+    /// From `PUSH_MOCK_ENDPOINT`, lets endpoints under it through for testing against a local mock push server.
+    mock_endpoint: Option<String>,
+}
+
+impl WebPush {
+    /// The key browsers need to subscribe, url safe base64 like the push api expects.
+    pub fn public_key(&self) -> &str {
+        &self.public_key
+    }
+
+    /// Only https endpoints of known push services, or the mock push server when one is set.
+    pub fn accepts_endpoint(&self, endpoint: &str) -> bool {
+        if let Some(mock_endpoint) = &self.mock_endpoint {
+            if endpoint.starts_with(mock_endpoint.as_str()) {
+                return true;
+            }
+        }
+
+        let Ok(uri) = endpoint.parse::<http::Uri>() else {
+            return false;
+        };
+
+        if uri.scheme_str() != Some("https") || uri.port_u16().is_some_and(|port| port != 443) {
+            return false;
+        }
+
+        let Some(host) = uri.host() else {
+            return false;
+        };
+
+        PUSH_SERVICE_HOSTS
+            .iter()
+            .any(|service| match service.strip_prefix('.') {
+                Some(domain) => host
+                    .strip_suffix(domain)
+                    .is_some_and(|subdomain| subdomain.ends_with('.')),
+                None => host == *service,
+            })
+    }
+}
+
+/// Reads the VAPID key from `VAPID_PRIVATE_KEY`, push is turned off when it isn't set.
+pub fn web_push_init() -> anyhow::Result<Option<WebPush>> {
+    let Ok(private_key) = dotenvy::var("VAPID_PRIVATE_KEY") else {
+        return Ok(None);
+    };
+
+    let signature_builder = VapidSignatureBuilder::from_base64_no_sub(&private_key)
+        .map_err(|error| anyhow::anyhow!("invalid VAPID_PRIVATE_KEY ({error})"))?;
+    let public_key = URL_SAFE_NO_PAD.encode(signature_builder.get_public_key());
+
+    Ok(Some(WebPush {
+        client: HyperWebPushClient::new(),
+        signature_builder,
+        subject: dotenvy::var("VAPID_SUBJECT")?,
+        public_key,
+        mock_endpoint: dotenvy::var("PUSH_MOCK_ENDPOINT").ok(),
+    }))
+}
+
+/// What the service worker turns into a system notification.
+#[derive(Serialize)]
+pub struct PushPayload {
+    pub title: String,
+    pub body: String,
+    pub url: String,
+}
+
+/// Sends the payload to every device the user subscribed.
+/// Subscriptions the push service no longer knows about are removed.
+pub async fn send_push(
+    state: &AppState,
+    user_id: i32,
+    payload: &PushPayload,
+) -> anyhow::Result<()> {
+    let Some(web_push) = &state.web_push else {
+        return Ok(());
+    };
+
+    let content = serde_json::to_vec(payload)?;
+
+    let subscriptions = sqlx::query!(
+        "SELECT id, endpoint, p256dh, auth FROM push_subscriptions WHERE user_id = $1",
+        user_id
+    )
+    .fetch_all(&state.pool)
+    .await?;
+
+    for subscription in subscriptions {
+        // stored before endpoints were checked
+        if !web_push.accepts_endpoint(&subscription.endpoint) {
+            tracing::warn!(
+                "removing push subscription({}) with an endpoint that isn't a push service",
+                subscription.id
+            );
+
+            sqlx::query!(
+                "DELETE FROM push_subscriptions WHERE id = $1",
+                subscription.id
+            )
+            .execute(&state.pool)
+            .await?;
+
+            continue;
+        }
+
+        let info = SubscriptionInfo::new(
+            subscription.endpoint,
+            subscription.p256dh,
+            subscription.auth,
+        );
+
+        let mut signature = web_push.signature_builder.clone().add_sub_info(&info);
+        signature.add_claim("sub", web_push.subject.as_str());
+
+        let mut message = WebPushMessageBuilder::new(&info);
+        message.set_payload(ContentEncoding::Aes128Gcm, &content);
+        message.set_ttl(PUSH_TTL_SECONDS);
+        message.set_vapid_signature(signature.build()?);
+
+        match web_push.client.send(message.build()?).await {
+            Ok(()) => {
+                sqlx::query!(
+                    "UPDATE push_subscriptions SET last_used_at = $1 WHERE id = $2",
+                    now(),
+                    subscription.id
+                )
+                .execute(&state.pool)
+                .await?;
+            }
+            Err(WebPushError::EndpointNotFound(_) | WebPushError::EndpointNotValid(_)) => {
+                tracing::debug!("push subscription({}) expired", subscription.id);
+
+                sqlx::query!(
+                    "DELETE FROM push_subscriptions WHERE id = $1",
+                    subscription.id
+                )
+                .execute(&state.pool)
+                .await?;
+            }
+            Err(error) => {
+                tracing::warn!(
+                    "failed to push to subscription({}) with error ({error})",
+                    subscription.id
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use std::io::Cursor;
+
+use sha2::{Digest, Sha256};
+
+/// Bump this when the look of identicons changes so browsers drop their cached ones.
+pub const IDENTICON_VERSION: &str = "identicon-1";
+
+const GRID: u32 = 5;
+
+/// A symmetric 5x5 pattern in a color picked from the hash of the username,
+/// so the same username always gets the same picture.
+/// This is cpu heavy so it should be run on the blocking pool.
+pub fn render_identicon(username: &str, size: u32) -> anyhow::Result<Vec<u8>> {
+    let hash = Sha256::digest(username.as_bytes());
+
+    let hue = u16::from_be_bytes([hash[0], hash[1]]) as f32 / u16::MAX as f32 * 360.0;
+    let foreground = hsl_to_rgb(hue, 0.55, 0.55);
+    let background = image::Rgb([240, 240, 240]);
+
+    // only the left half and middle column are picked, the right half mirrors them
+    let columns = GRID.div_ceil(2);
+    let filled = |column: u32, row: u32| {
+        let column = column.min(GRID - 1 - column);
+        let bit = row * columns + column;
+        hash[2 + (bit / 8) as usize] & (1 << (bit % 8)) != 0
+    };
+
+    let margin = size / 10;
+    let cell = (size - margin * 2) as f32 / GRID as f32;
+
+    let img = image::RgbImage::from_fn(size, size, |x, y| {
+        if x < margin || y < margin || x >= size - margin || y >= size - margin {
+            return background;
+        }
+
+        let column = (((x - margin) as f32 / cell) as u32).min(GRID - 1);
+        let row = (((y - margin) as f32 / cell) as u32).min(GRID - 1);
+
+        if filled(column, row) {
+            foreground
+        } else {
+            background
+        }
+    });
+
+    let mut png = Vec::new();
+    img.write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)?;
+
+    Ok(png)
+}
+
+/// Etags for identicons only depend on the username and the size.
+pub fn identicon_etag(username: &str, size: u32) -> String {
+    let hash = Sha256::digest(format!("{IDENTICON_VERSION}/{size}/{username}").as_bytes());
+    format!("\"{}\"", &hex::encode(hash)[..32])
+}
+
+fn hsl_to_rgb(hue: f32, saturation: f32, lightness: f32) -> image::Rgb<u8> {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let m = lightness - chroma / 2.0;
+
+    let (r, g, b) = match hue as u32 {
+        0..=59 => (chroma, x, 0.0),
+        60..=119 => (x, chroma, 0.0),
+        120..=179 => (0.0, chroma, x),
+        180..=239 => (0.0, x, chroma),
+        240..=299 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+
+    image::Rgb([
+        ((r + m) * 255.0).round() as u8,
+        ((g + m) * 255.0).round() as u8,
+        ((b + m) * 255.0).round() as u8,
+    ])
+}
@@ -0,0 +1,418 @@
+use std::{io::Cursor, time::SystemTime};
+
+use axum::{
+    extract::{Path, Query, State},
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+    Router,
+};
+use http::{header, HeaderMap, HeaderValue, StatusCode};
+use serde::Deserialize;
+
+use self::identicon::{identicon_etag, render_identicon, IDENTICON_VERSION};
+use crate::{
+    data::app_state::AppState,
+    utils::{percent_encode, ToServerError},
+};
+
+mod identicon;
+
+/// Every profile picture is pre-rendered at these sizes in every [`AvatarFormat`].
+pub const AVATAR_SIZES: [u32; 3] = [40, 128, 512];
+
+pub fn profile_picture_routes() -> Router<AppState> {
+    Router::new()
+        .route("/:username", get(current_profile_picture))
+        .route("/:username/:version/:size", get(profile_picture))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AvatarFormat {
+    Avif,
+    Png,
+}
+
+impl AvatarFormat {
+    const ALL: [AvatarFormat; 2] = [AvatarFormat::Avif, AvatarFormat::Png];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AvatarFormat::Avif => "avif",
+            AvatarFormat::Png => "png",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            AvatarFormat::Avif => "image/avif",
+            AvatarFormat::Png => "image/png",
+        }
+    }
+
+    fn output_format(&self) -> image::ImageOutputFormat {
+        match self {
+            AvatarFormat::Avif => image::ImageOutputFormat::Avif,
+            AvatarFormat::Png => image::ImageOutputFormat::Png,
+        }
+    }
+
+    /// Browsers that don't list avif in their `Accept` header get png.
+    fn from_accept(headers: &HeaderMap) -> Self {
+        let accepts_avif = headers
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| accept.contains("image/avif"));
+
+        if accepts_avif {
+            AvatarFormat::Avif
+        } else {
+            AvatarFormat::Png
+        }
+    }
+}
+
+pub struct AvatarVariant {
+    pub size: i32,
+    pub format: AvatarFormat,
+    pub data: Vec<u8>,
+}
+
+/// Renders every size and format of a profile picture.
+/// This is cpu heavy so it should be run on the blocking pool.
+pub fn render_variants(img: &image::DynamicImage) -> anyhow::Result<Vec<AvatarVariant>> {
+    let mut variants = vec![];
+
+    for size in AVATAR_SIZES {
+        let resized = img.resize_to_fill(size, size, image::imageops::FilterType::Lanczos3);
+
+        for format in AvatarFormat::ALL {
+            let mut data = Vec::new();
+            resized.write_to(&mut Cursor::new(&mut data), format.output_format())?;
+
+            variants.push(AvatarVariant {
+                size: size as i32,
+                format,
+                data,
+            });
+        }
+    }
+
+    Ok(variants)
+}
+
+/// Changes whenever the picture does so the versioned urls can be cached forever.
+/// Users without a picture get their identicon.
+fn version(picture_key: Option<&str>) -> &str {
+    match picture_key {
+        Some(picture_key) => &picture_key[..16.min(picture_key.len())],
+        None => IDENTICON_VERSION,
+    }
+}
+
+/// The smallest pre-rendered size that is at least as big as `size`, or the biggest one.
+fn variant_size(size: u32) -> u32 {
+    AVATAR_SIZES
+        .into_iter()
+        .find(|&variant| variant >= size)
+        .unwrap_or(AVATAR_SIZES[AVATAR_SIZES.len() - 1])
+}
+
+/// The url pages embed, it changes with the picture so browsers never have to check it again.
+pub fn profile_picture_url(username: &str, picture_key: Option<&str>, size: u32) -> String {
+    format!(
+        "/profile_pictures/{}/{}/{}",
+        percent_encode(username),
+        version(picture_key),
+        variant_size(size)
+    )
+}
+
+#[derive(Deserialize)]
+struct SizeQuery {
+    size: Option<u32>,
+}
+
+/// Redirects to the versioned url of the current picture at the smallest size that is at least as big as requested.
+/// Pages link the versioned url directly, this is for links from outside.
+async fn current_profile_picture(
+    Path(username): Path<String>,
+    Query(query): Query<SizeQuery>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let picture_key = sqlx::query!(
+        "SELECT profile_picture_key FROM users WHERE username = $1",
+        username
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .server_error()?
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        String::from("Profile Picture Not Found"),
+    ))?
+    .profile_picture_key;
+
+    let location = profile_picture_url(
+        &username,
+        picture_key.as_deref(),
+        query.size.unwrap_or(u32::MAX),
+    );
+
+    let mut headers = HeaderMap::new();
+
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+
+    Ok((headers, Redirect::temporary(&location)))
+}
+
+async fn profile_picture(
+    Path((username, version_text, size)): Path<(String, String, u32)>,
+    State(state): State<AppState>,
+    request_headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    if !AVATAR_SIZES.contains(&size) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            String::from("Profile Picture Not Found"),
+        ));
+    }
+
+    let user = sqlx::query!(
+        "SELECT id, profile_picture_key, profile_picture_updated_at FROM users WHERE username = $1",
+        username
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .server_error()?
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        String::from("Profile Picture Not Found"),
+    ))?;
+
+    let current_version = version(user.profile_picture_key.as_deref());
+
+    if current_version != version_text {
+        // an old url, the picture has been changed since
+        return Ok(Redirect::temporary(&format!(
+            "/profile_pictures/{}/{current_version}/{size}",
+            percent_encode(&username)
+        ))
+        .into_response());
+    }
+
+    let Some(picture_key) = user.profile_picture_key else {
+        return identicon(username, size, &request_headers).await;
+    };
+
+    let format = AvatarFormat::from_accept(&request_headers);
+
+    let variant_key = sqlx::query!(
+        "SELECT storage_key FROM profile_picture_variants WHERE user_id = $1 AND size = $2 AND format = $3",
+        user.id,
+        size as i32,
+        format.name()
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .server_error()?
+    .map(|rec| rec.storage_key);
+
+    // pictures uploaded before there were variants only have the original avif
+    let (key, content_type) = match variant_key {
+        Some(variant_key) => (variant_key, format.content_type()),
+        None => (picture_key, AvatarFormat::Avif.content_type()),
+    };
+
+    let etag = format!("\"{}\"", &key[..32.min(key.len())]);
+    let last_modified = user
+        .profile_picture_updated_at
+        .map(|updated_at| SystemTime::from(updated_at.assume_utc()));
+
+    let mut headers = cache_headers(&etag, last_modified)?;
+    headers.insert(header::VARY, HeaderValue::from_static("Accept"));
+
+    if not_modified(&request_headers, &etag, last_modified) {
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+
+    let body = state.storage.get(&key).await.server_error()?.ok_or((
+        StatusCode::NOT_FOUND,
+        String::from("Profile Picture Not Found"),
+    ))?;
+
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_static("inline"),
+    );
+
+    Ok((headers, body).into_response())
+}
+
+async fn identicon(
+    username: String,
+    size: u32,
+    request_headers: &HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    let etag = identicon_etag(&username, size);
+    let mut headers = cache_headers(&etag, None)?;
+
+    if not_modified(request_headers, &etag, None) {
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+
+    let body = tokio::task::spawn_blocking(move || render_identicon(&username, size))
+        .await
+        .server_error()?
+        .server_error()?;
+
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(AvatarFormat::Png.content_type()),
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_static("inline"),
+    );
+
+    Ok((headers, body).into_response())
+}
+
+fn cache_headers(
+    etag: &str,
+    last_modified: Option<SystemTime>,
+) -> Result<HeaderMap, (StatusCode, String)> {
+    let mut headers = HeaderMap::new();
+
+    headers.insert(header::ETAG, HeaderValue::from_str(etag).server_error()?);
+    if let Some(last_modified) = last_modified {
+        headers.insert(
+            header::LAST_MODIFIED,
+            HeaderValue::from_str(&httpdate::fmt_http_date(last_modified)).server_error()?,
+        );
+    }
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static("public, max-age=31536000, immutable"),
+    );
+
+    Ok(headers)
+}
+
+/// `If-None-Match` takes priority over `If-Modified-Since` when both are sent.
+fn not_modified(headers: &HeaderMap, etag: &str, last_modified: Option<SystemTime>) -> bool {
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
+        return if_none_match.to_str().is_ok_and(|if_none_match| {
+            if_none_match
+                .split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == etag || tag == "*")
+        });
+    }
+
+    let if_modified_since = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|since| since.to_str().ok())
+        .and_then(|since| httpdate::parse_http_date(since).ok());
+
+    match (if_modified_since, last_modified) {
+        (Some(since), Some(last_modified)) => {
+            // http dates only have second precision
+            last_modified
+                .duration_since(since)
+                .map_or(true, |newer_by| newer_by.as_secs() == 0)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn version_is_a_prefix_of_the_key() {
+        assert_eq!(version(Some("0123456789abcdef0123")), "0123456789abcdef");
+        assert_eq!(version(Some("short")), "short");
+        assert_eq!(version(None), IDENTICON_VERSION);
+    }
+
+    #[test]
+    fn sizes_round_up_to_a_variant() {
+        assert_eq!(variant_size(1), 40);
+        assert_eq!(variant_size(40), 40);
+        assert_eq!(variant_size(41), 128);
+        assert_eq!(variant_size(4096), 512);
+    }
+
+    #[test]
+    fn url_is_versioned_and_encoded() {
+        assert_eq!(
+            profile_picture_url("a b", Some("0123456789abcdef0123"), 100),
+            "/profile_pictures/a%20b/0123456789abcdef/128"
+        );
+    }
+
+    #[test]
+    fn format_follows_the_accept_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(AvatarFormat::from_accept(&headers), AvatarFormat::Png);
+
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("image/avif,image/webp,*/*"),
+        );
+        assert_eq!(AvatarFormat::from_accept(&headers), AvatarFormat::Avif);
+    }
+
+    #[test]
+    fn etag_match_is_not_modified() {
+        let mut headers = HeaderMap::new();
+        assert!(!not_modified(&headers, "\"abc\"", None));
+
+        headers.insert(
+            header::IF_NONE_MATCH,
+            HeaderValue::from_static("\"xyz\", W/\"abc\""),
+        );
+        assert!(not_modified(&headers, "\"abc\"", None));
+        assert!(!not_modified(&headers, "\"def\"", None));
+    }
+
+    #[test]
+    fn etag_takes_priority_over_date() {
+        let last_modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::IF_MODIFIED_SINCE,
+            HeaderValue::from_str(&httpdate::fmt_http_date(last_modified)).unwrap(),
+        );
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"xyz\""));
+
+        assert!(!not_modified(&headers, "\"abc\"", Some(last_modified)));
+    }
+
+    #[test]
+    fn unchanged_since_date_is_not_modified() {
+        let last_modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::IF_MODIFIED_SINCE,
+            HeaderValue::from_str(&httpdate::fmt_http_date(last_modified)).unwrap(),
+        );
+
+        // sub-second differences are lost in http dates
+        assert!(not_modified(
+            &headers,
+            "\"abc\"",
+            Some(last_modified + Duration::from_millis(500))
+        ));
+        assert!(!not_modified(
+            &headers,
+            "\"abc\"",
+            Some(last_modified + Duration::from_secs(5))
+        ));
+        assert!(!not_modified(&headers, "\"abc\"", None));
+    }
+}
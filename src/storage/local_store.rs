@@ -0,0 +1,65 @@
+use std::path::PathBuf;
+
+use axum::async_trait;
+use tokio::fs;
+
+use super::BlobStore;
+
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub async fn new(root: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root).await?;
+        Ok(Self { root })
+    }
+
+    fn path(&self, key: &str) -> anyhow::Result<PathBuf> {
+        if key.len() < 4 || !key.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(anyhow::anyhow!("invalid storage key ({key})"));
+        }
+
+        // spread files over sub directories so none get too big
+        Ok(self.root.join(&key[..2]).join(&key[2..4]).join(key))
+    }
+}
+
+#[async_trait]
+impl BlobStore for LocalStore {
+    async fn put(&self, key: &str, data: &[u8]) -> anyhow::Result<()> {
+        let path = self.path(key)?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        // write then rename so a half written file is never read
+        let temp_path = path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+        fs::write(&temp_path, data).await?;
+        fs::rename(&temp_path, &path).await?;
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        match fs::read(self.path(key)?).await {
+            Ok(data) => Ok(Some(data)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    async fn exists(&self, key: &str) -> anyhow::Result<bool> {
+        Ok(fs::try_exists(self.path(key)?).await?)
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        match fs::remove_file(self.path(key)?).await {
+            Ok(()) => Ok(()),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(error.into()),
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use axum::async_trait;
+use sha2::{Digest, Sha256};
+use sqlx::{PgConnection, PgPool};
+
+use self::{local_store::LocalStore, s3_store::S3Store};
+
+mod local_store;
+mod s3_store;
+
+/// Somewhere to keep large binary files like profile pictures and attachments
+/// so they don't live in database rows.
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    async fn put(&self, key: &str, data: &[u8]) -> anyhow::Result<()>;
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>>;
+    async fn exists(&self, key: &str) -> anyhow::Result<bool>;
+    async fn delete(&self, key: &str) -> anyhow::Result<()>;
+}
+
+pub type Storage = Arc<dyn BlobStore>;
+
+/// Picks the backend with the `STORAGE_BACKEND` env var, `local` or `s3`.
+pub async fn storage_init() -> anyhow::Result<Storage> {
+    let backend = dotenvy::var("STORAGE_BACKEND").unwrap_or_else(|_| String::from("local"));
+
+    match backend.as_str() {
+        "local" => {
+            let path = dotenvy::var("STORAGE_PATH").unwrap_or_else(|_| String::from("storage/"));
+            Ok(Arc::new(LocalStore::new(path).await?))
+        }
+        "s3" => Ok(Arc::new(S3Store::new(
+            &dotenvy::var("S3_BUCKET")?,
+            &dotenvy::var("S3_REGION")?,
+            &dotenvy::var("S3_ENDPOINT")?,
+            &dotenvy::var("S3_ACCESS_KEY")?,
+            &dotenvy::var("S3_SECRET_KEY")?,
+        )?)),
+        _ => Err(anyhow::anyhow!("unknown storage backend ({backend})")),
+    }
+}
+
+/// Stores data under the hash of its contents so identical files are only kept once.
+/// The key stays locked until `transaction` ends so [`release`] can't delete the blob before
+/// the row that refers to it is committed. If the transaction fails the key has to be released.
+pub async fn store(
+    storage: &dyn BlobStore,
+    data: &[u8],
+    transaction: &mut PgConnection,
+) -> anyhow::Result<String> {
+    let key = hex::encode(Sha256::digest(data));
+
+    lock_key(&key, transaction).await?;
+
+    if !storage.exists(&key).await? {
+        storage.put(&key, data).await?;
+    }
+
+    Ok(key)
+}
+
+/// Deletes a blob once nothing in the database refers to it anymore.
+pub async fn release(storage: &dyn BlobStore, pool: &PgPool, key: &str) -> anyhow::Result<()> {
+    let mut transaction = pool.begin().await?;
+
+    lock_key(key, &mut transaction).await?;
+
+    let in_use = sqlx::query!(
+        r#"SELECT EXISTS (SELECT 1 FROM users WHERE profile_picture_key = $1)
+            OR EXISTS (SELECT 1 FROM profile_picture_variants WHERE storage_key = $1)
+            OR EXISTS (SELECT 1 FROM chat_attachments WHERE storage_key = $1 OR thumbnail_key = $1)
+            OR EXISTS (SELECT 1 FROM data_exports WHERE storage_key = $1) AS "in_use!""#,
+        key
+    )
+    .fetch_one(&mut *transaction)
+    .await?
+    .in_use;
+
+    if !in_use {
+        storage.delete(key).await?;
+    }
+
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+/// Releases what was stored for a transaction that didn't commit, failures are only logged
+/// because the caller is already handling an error.
+pub async fn release_all(storage: &dyn BlobStore, pool: &PgPool, keys: &[String]) {
+    for key in keys {
+        if let Err(error) = release(storage, pool, key).await {
+            tracing::error!("Failed to release blob({key}) with error ({error})");
+        }
+    }
+}
+
+/// Storing and releasing the same key wait for each other.
+async fn lock_key(key: &str, connection: &mut PgConnection) -> anyhow::Result<()> {
+    sqlx::query!("SELECT pg_advisory_xact_lock(hashtext($1))", key)
+        .execute(connection)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use axum::async_trait;
+use s3::{creds::Credentials, Bucket, Region};
+
+use super::BlobStore;
+
+/// Works with any s3 compatible service like aws or a local minio.
+pub struct S3Store {
+    bucket: Bucket,
+}
+
+impl S3Store {
+    pub fn new(
+        bucket: &str,
+        region: &str,
+        endpoint: &str,
+        access_key: &str,
+        secret_key: &str,
+    ) -> anyhow::Result<Self> {
+        let region = Region::Custom {
+            region: region.to_owned(),
+            endpoint: endpoint.to_owned(),
+        };
+        let credentials = Credentials::new(Some(access_key), Some(secret_key), None, None, None)?;
+
+        Ok(Self {
+            bucket: Bucket::new(bucket, region, credentials)?.with_path_style(),
+        })
+    }
+}
+
+#[async_trait]
+impl BlobStore for S3Store {
+    async fn put(&self, key: &str, data: &[u8]) -> anyhow::Result<()> {
+        let response = self.bucket.put_object(key, data).await?;
+
+        match response.status_code() {
+            200..=299 => Ok(()),
+            status => Err(anyhow::anyhow!(
+                "s3 put of ({key}) failed with status ({status})"
+            )),
+        }
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let response = self.bucket.get_object(key).await?;
+
+        match response.status_code() {
+            200..=299 => Ok(Some(response.to_vec())),
+            404 => Ok(None),
+            status => Err(anyhow::anyhow!(
+                "s3 get of ({key}) failed with status ({status})"
+            )),
+        }
+    }
+
+    async fn exists(&self, key: &str) -> anyhow::Result<bool> {
+        let (_, status) = self.bucket.head_object(key).await?;
+
+        match status {
+            200..=299 => Ok(true),
+            404 => Ok(false),
+            status => Err(anyhow::anyhow!(
+                "s3 head of ({key}) failed with status ({status})"
+            )),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        let response = self.bucket.delete_object(key).await?;
+
+        match response.status_code() {
+            200..=299 | 404 => Ok(()),
+            status => Err(anyhow::anyhow!(
+                "s3 delete of ({key}) failed with status ({status})"
+            )),
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::time::Duration;
+
+use sqlx::{Postgres, Transaction};
+
+use crate::{data::app_state::AppState, storage};
+
+use super::now;
+
+/// How long users have to change their mind after asking for their account to be deleted.
+pub const COOLING_OFF_PERIOD: time::Duration = time::Duration::days(7);
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+pub const DELETED_DISPLAY_NAME: &str = "Deleted user";
+
+pub async fn run(state: AppState) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(error) = delete_due_accounts(&state).await {
+            tracing::error!("Failed to delete accounts with error ({error})");
+        }
+    }
+}
+
+async fn delete_due_accounts(state: &AppState) -> anyhow::Result<()> {
+    loop {
+        let mut transaction = state.pool.begin().await?;
+
+        // skip locked so several servers can share the work
+        let Some(user_id) = sqlx::query!(
+            "SELECT user_id FROM account_deletions WHERE delete_after <= $1 ORDER BY delete_after LIMIT 1 FOR UPDATE SKIP LOCKED",
+            now()
+        )
+        .fetch_optional(&mut *transaction)
+        .await?
+        .map(|rec| rec.user_id) else {
+            return Ok(());
+        };
+
+        let released_keys = anonymize_user(user_id, &mut transaction).await?;
+
+        transaction.commit().await?;
+
+        for key in released_keys {
+            storage::release(&*state.storage, &state.pool, &key).await?;
+        }
+
+        tracing::info!("deleted account of user({user_id})");
+    }
+}
+
+/// Removes everything about a user except the messages they sent, which stay in the
+/// conversations of the people they talked to under a "Deleted user" tombstone.
+/// Returns the storage keys that were referenced by the user.
+async fn anonymize_user(
+    user_id: i32,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> anyhow::Result<Vec<String>> {
+    let mut keys = vec![];
+
+    sqlx::query!("DELETE FROM auth_tokens WHERE user_id = $1", user_id)
+        .execute(&mut **transaction)
+        .await?;
+    sqlx::query!("DELETE FROM account_activation WHERE id = $1", user_id)
+        .execute(&mut **transaction)
+        .await?;
+    sqlx::query!(
+        "DELETE FROM friend_requests WHERE sender_id = $1 OR recipient_id = $1",
+        user_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM friends WHERE user_id = $1 OR friend_id = $1",
+        user_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM blocked_users WHERE user_id = $1 OR blocked_id = $1",
+        user_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    // messages linking to the old username get rendered again without the link
+    sqlx::query!(
+        "UPDATE chat_messages SET msg_html = NULL WHERE id IN (SELECT message_id FROM mentions WHERE user_id = $1)",
+        user_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!("DELETE FROM mentions WHERE user_id = $1", user_id)
+        .execute(&mut **transaction)
+        .await?;
+    sqlx::query!("DELETE FROM notifications WHERE user_id = $1", user_id)
+        .execute(&mut **transaction)
+        .await?;
+    sqlx::query!("DELETE FROM push_subscriptions WHERE user_id = $1", user_id)
+        .execute(&mut **transaction)
+        .await?;
+    sqlx::query!(
+        "DELETE FROM notification_preferences WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!("DELETE FROM email_digests WHERE user_id = $1", user_id)
+        .execute(&mut **transaction)
+        .await?;
+    sqlx::query!(
+        "DELETE FROM conversation_reads WHERE user_id = $1 OR other_user_id = $1",
+        user_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM conversation_settings WHERE user_id = $1 OR other_user_id = $1",
+        user_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!("DELETE FROM starred_messages WHERE user_id = $1", user_id)
+        .execute(&mut **transaction)
+        .await?;
+    sqlx::query!(
+        "DELETE FROM scheduled_messages WHERE sender_id = $1 OR recipient_id = $1",
+        user_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM disappearing_messages WHERE user_a = $1 OR user_b = $1 OR changed_by = $1",
+        user_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM message_drafts WHERE user_id = $1 OR other_user_id = $1",
+        user_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    keys.extend(
+        sqlx::query!(
+            "DELETE FROM profile_picture_variants WHERE user_id = $1 RETURNING storage_key",
+            user_id
+        )
+        .fetch_all(&mut **transaction)
+        .await?
+        .into_iter()
+        .map(|rec| rec.storage_key),
+    );
+    keys.extend(
+        sqlx::query!(
+            "DELETE FROM data_exports WHERE user_id = $1 RETURNING storage_key",
+            user_id
+        )
+        .fetch_all(&mut **transaction)
+        .await?
+        .into_iter()
+        .filter_map(|rec| rec.storage_key),
+    );
+
+    keys.extend(
+        sqlx::query!(
+            "SELECT profile_picture_key FROM users WHERE id = $1",
+            user_id
+        )
+        .fetch_one(&mut **transaction)
+        .await?
+        .profile_picture_key,
+    );
+
+    // the username and email have to stay unique so they get a random placeholder
+    let placeholder = format!("deleted-{}", uuid::Uuid::new_v4().simple());
+
+    sqlx::query!(
+        "UPDATE users SET
+            username = $1,
+            display_name = $2,
+            email = $1 || '@deleted.invalid',
+            password_hash = '',
+            profile_picture_key = NULL,
+            profile_picture_updated_at = NULL,
+            bio = NULL,
+            status = NULL,
+            activated = false,
+            deleted_at = $3
+        WHERE id = $4",
+        placeholder,
+        DELETED_DISPLAY_NAME,
+        now(),
+        user_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    sqlx::query!("DELETE FROM account_deletions WHERE user_id = $1", user_id)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(keys)
+}
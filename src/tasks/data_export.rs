@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+
+use base64::Engine;
+use serde::Serialize;
+use sqlx::{Postgres, Transaction};
+use time::format_description::well_known::Rfc3339;
+
+use crate::{data::app_state::AppState, storage};
+
+use super::now;
+
+#[derive(Serialize)]
+struct DataExport {
+    exported_at: String,
+    profile: ProfileExport,
+    friends: Vec<String>,
+    blocked: Vec<String>,
+    messages: Vec<MessageExport>,
+    /// The original avif, base64 encoded.
+    profile_picture: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ProfileExport {
+    username: String,
+    display_name: Option<String>,
+    email: String,
+    bio: Option<String>,
+    status: Option<String>,
+    created_at: Option<String>,
+    friends_only_messages: bool,
+    friends_only_profile: bool,
+}
+
+#[derive(Serialize)]
+struct MessageExport {
+    id: i32,
+    from: String,
+    to: String,
+    sent_at: String,
+    /// "user" or "system".
+    kind: String,
+    message: String,
+    attachments: Vec<AttachmentExport>,
+}
+
+#[derive(Serialize)]
+struct AttachmentExport {
+    file_name: String,
+    content_type: String,
+    size: i32,
+}
+
+/// Picks up exports that were interrupted by a restart.
+pub async fn resume_pending(state: AppState) {
+    loop {
+        let result = async {
+            let mut transaction = state.pool.begin().await?;
+
+            // skip locked so several servers can share the work
+            let Some(export_id) = sqlx::query!(
+                "SELECT id FROM data_exports WHERE finished_at IS NULL AND NOT failed ORDER BY id LIMIT 1 FOR UPDATE SKIP LOCKED"
+            )
+            .fetch_optional(&mut *transaction)
+            .await?
+            .map(|rec| rec.id) else {
+                return Ok(false);
+            };
+
+            finish_export(&state, export_id, transaction).await?;
+
+            anyhow::Ok(true)
+        }
+        .await;
+
+        match result {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(error) => {
+                tracing::error!("Failed to resume data exports with error ({error})");
+                return;
+            }
+        }
+    }
+}
+
+pub async fn run_export(state: AppState, export_id: i32) {
+    let result = async {
+        let mut transaction = state.pool.begin().await?;
+
+        // another server resuming exports may have claimed it already
+        let claimed = sqlx::query!(
+            "SELECT id FROM data_exports WHERE id = $1 AND finished_at IS NULL AND NOT failed FOR UPDATE SKIP LOCKED",
+            export_id
+        )
+        .fetch_optional(&mut *transaction)
+        .await?
+        .is_some();
+
+        if claimed {
+            finish_export(&state, export_id, transaction).await?;
+        }
+
+        anyhow::Ok(())
+    }
+    .await;
+
+    if let Err(error) = result {
+        tracing::error!("Failed to save data export({export_id}) with error ({error})");
+    }
+}
+
+/// Builds a claimed export, the row stays locked by `transaction` until it is finished or failed.
+async fn finish_export(
+    state: &AppState,
+    export_id: i32,
+    mut transaction: Transaction<'_, Postgres>,
+) -> anyhow::Result<()> {
+    match build_export(state, export_id).await {
+        Ok(data) => save_export(state, export_id, &data, transaction).await,
+        Err(error) => {
+            tracing::error!("Failed to build data export({export_id}) with error ({error})");
+
+            sqlx::query!(
+                "UPDATE data_exports SET failed = true WHERE id = $1",
+                export_id
+            )
+            .execute(&mut *transaction)
+            .await?;
+
+            transaction.commit().await?;
+
+            Ok(())
+        }
+    }
+}
+
+/// Stores the file and marks the export finished together, the blob is released again if that fails.
+async fn save_export(
+    state: &AppState,
+    export_id: i32,
+    data: &[u8],
+    mut transaction: Transaction<'_, Postgres>,
+) -> anyhow::Result<()> {
+    let storage_key = storage::store(&*state.storage, data, &mut transaction).await?;
+
+    let result = sqlx::query!(
+        "UPDATE data_exports SET finished_at = $1, storage_key = $2 WHERE id = $3",
+        now(),
+        storage_key,
+        export_id
+    )
+    .execute(&mut *transaction)
+    .await;
+
+    let result = match result {
+        Ok(_) => transaction.commit().await,
+        // rolled back first so the key isn't locked anymore
+        Err(error) => {
+            drop(transaction);
+            Err(error)
+        }
+    };
+
+    if let Err(error) = result {
+        storage::release_all(&*state.storage, &state.pool, &[storage_key]).await;
+        return Err(error.into());
+    }
+
+    Ok(())
+}
+
+async fn build_export(state: &AppState, export_id: i32) -> anyhow::Result<Vec<u8>> {
+    let user_id = sqlx::query!("SELECT user_id FROM data_exports WHERE id = $1", export_id)
+        .fetch_one(&state.pool)
+        .await?
+        .user_id;
+
+    let user = sqlx::query!(
+        "SELECT username, display_name, email, bio, status, created_at, friends_only_messages, friends_only_profile, profile_picture_key FROM users WHERE id = $1",
+        user_id
+    )
+    .fetch_one(&state.pool)
+    .await?;
+
+    let friends = sqlx::query!(
+        "SELECT username FROM friends JOIN users ON users.id = friends.friend_id WHERE user_id = $1 ORDER BY since",
+        user_id
+    )
+    .fetch_all(&state.pool)
+    .await?
+    .into_iter()
+    .map(|rec| rec.username)
+    .collect();
+
+    let blocked = sqlx::query!(
+        "SELECT username FROM blocked_users JOIN users ON users.id = blocked_users.blocked_id WHERE user_id = $1 ORDER BY blocked_at",
+        user_id
+    )
+    .fetch_all(&state.pool)
+    .await?
+    .into_iter()
+    .map(|rec| rec.username)
+    .collect();
+
+    let mut attachments: HashMap<i32, Vec<AttachmentExport>> = HashMap::new();
+
+    for rec in sqlx::query!(
+        "SELECT message_id, file_name, content_type, size FROM chat_attachments
+        JOIN chat_messages ON chat_messages.id = chat_attachments.message_id
+        WHERE sender_id = $1 OR recipient_id = $1
+        ORDER BY chat_attachments.id",
+        user_id
+    )
+    .fetch_all(&state.pool)
+    .await?
+    {
+        attachments
+            .entry(rec.message_id)
+            .or_default()
+            .push(AttachmentExport {
+                file_name: rec.file_name,
+                content_type: rec.content_type,
+                size: rec.size,
+            });
+    }
+
+    let messages = sqlx::query!(
+        r#"SELECT chat_messages.id, senders.username AS "from", recipients.username AS "to", msg, sent_at, kind FROM chat_messages
+        JOIN users AS senders ON senders.id = chat_messages.sender_id
+        JOIN users AS recipients ON recipients.id = chat_messages.recipient_id
+        WHERE sender_id = $1 OR recipient_id = $1
+        ORDER BY sent_at, chat_messages.id"#,
+        user_id
+    )
+    .fetch_all(&state.pool)
+    .await?
+    .into_iter()
+    .map(|rec| {
+        Ok(MessageExport {
+            id: rec.id,
+            from: rec.from,
+            to: rec.to,
+            sent_at: rec.sent_at.assume_utc().format(&Rfc3339)?,
+            kind: rec.kind,
+            message: rec.msg,
+            attachments: attachments.remove(&rec.id).unwrap_or_default(),
+        })
+    })
+    .collect::<anyhow::Result<_>>()?;
+
+    let profile_picture = match &user.profile_picture_key {
+        Some(key) => state
+            .storage
+            .get(key)
+            .await?
+            .map(|picture| base64::engine::general_purpose::STANDARD.encode(picture)),
+        None => None,
+    };
+
+    let export = DataExport {
+        exported_at: now().assume_utc().format(&Rfc3339)?,
+        profile: ProfileExport {
+            username: user.username,
+            display_name: user.display_name,
+            email: user.email,
+            bio: user.bio,
+            status: user.status,
+            created_at: user
+                .created_at
+                .map(|created_at| created_at.assume_utc().format(&Rfc3339))
+                .transpose()?,
+            friends_only_messages: user.friends_only_messages,
+            friends_only_profile: user.friends_only_profile,
+        },
+        friends,
+        blocked,
+        messages,
+        profile_picture,
+    };
+
+    let data = serde_json::to_vec_pretty(&export)?;
+
+    tracing::debug!("built data export({export_id}) for user({user_id})");
+
+    Ok(data)
+}
@@ -0,0 +1,98 @@
+use std::{collections::HashSet, time::Duration};
+
+use crate::{data::app_state::AppState, storage};
+
+use super::now;
+
+/// Expired messages are gone at most this long after their timer runs out.
+const CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Messages deleted in one transaction.
+const BATCH_SIZE: i64 = 100;
+
+pub async fn run(state: AppState) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(error) = delete_expired_messages(&state).await {
+            tracing::error!("Failed to delete expired messages with error ({error})");
+        }
+    }
+}
+
+async fn delete_expired_messages(state: &AppState) -> anyhow::Result<()> {
+    loop {
+        let mut transaction = state.pool.begin().await?;
+
+        let expired = sqlx::query!(
+            "SELECT id, sender_id, recipient_id FROM chat_messages
+            WHERE expires_at <= $1
+            ORDER BY expires_at, id LIMIT $2 FOR UPDATE SKIP LOCKED",
+            now(),
+            BATCH_SIZE
+        )
+        .fetch_all(&mut *transaction)
+        .await?;
+
+        if expired.is_empty() {
+            return Ok(());
+        }
+
+        let ids = expired.iter().map(|rec| rec.id).collect::<Vec<_>>();
+
+        let mut keys = Vec::new();
+        for rec in sqlx::query!(
+            "DELETE FROM chat_attachments WHERE message_id = ANY($1) RETURNING storage_key, thumbnail_key",
+            &ids
+        )
+        .fetch_all(&mut *transaction)
+        .await?
+        {
+            keys.push(rec.storage_key);
+            keys.extend(rec.thumbnail_key);
+        }
+
+        sqlx::query!("DELETE FROM mentions WHERE message_id = ANY($1)", &ids)
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query!("DELETE FROM notifications WHERE message_id = ANY($1)", &ids)
+            .execute(&mut *transaction)
+            .await?;
+        sqlx::query!(
+            "DELETE FROM pinned_messages WHERE message_id = ANY($1)",
+            &ids
+        )
+        .execute(&mut *transaction)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM starred_messages WHERE message_id = ANY($1)",
+            &ids
+        )
+        .execute(&mut *transaction)
+        .await?;
+        sqlx::query!("DELETE FROM chat_messages WHERE id = ANY($1)", &ids)
+            .execute(&mut *transaction)
+            .await?;
+
+        transaction.commit().await?;
+
+        // blobs can only go once nothing points at them anymore
+        for key in keys {
+            storage::release(&*state.storage, &state.pool, &key).await?;
+        }
+
+        tracing::debug!("deleted {} expired messages", ids.len());
+
+        let conversations = expired
+            .iter()
+            .map(|rec| (rec.sender_id, rec.recipient_id))
+            .collect::<HashSet<_>>();
+
+        for conversation in conversations {
+            // nobody listening is fine, the messages are already gone for the next page load
+            let _ = state.messages_deleted.send(conversation);
+        }
+    }
+}
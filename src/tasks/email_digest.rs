@@ -0,0 +1,275 @@
+use std::time::Duration;
+
+use askama::Template;
+use lettre::{
+    message::header::{ContentType, Header, HeaderName, HeaderValue},
+    Message, Transport,
+};
+
+use crate::{
+    api::account::email_digest::{unsubscribe_token, DigestFrequency},
+    data::app_state::AppState,
+    utils::username::Username,
+};
+
+use super::now;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Messages only go in a digest once the user has had this long to read them on the site.
+const AWAY_FOR: time::Duration = time::Duration::hours(1);
+
+pub async fn run(state: AppState) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(error) = send_due_digests(&state).await {
+            tracing::error!("Failed to send email digests with error ({error})");
+        }
+    }
+}
+
+struct UnreadConversation {
+    sender: Username,
+    count: i64,
+}
+
+#[derive(Template)]
+#[template(path = "email/digest_email.html")]
+struct DigestEmailTemplate {
+    username: Username,
+    conversations: Vec<UnreadConversation>,
+    unsubscribe_token: String,
+}
+
+impl DigestEmailTemplate {
+    fn total(&self) -> i64 {
+        self.conversations
+            .iter()
+            .map(|conversation| conversation.count)
+            .sum()
+    }
+}
+
+async fn send_due_digests(state: &AppState) -> anyhow::Result<()> {
+    // users who never changed the setting get the default
+    sqlx::query!(
+        "INSERT INTO email_digests(user_id, frequency, covered_until, next_digest_at)
+        SELECT id, $1, $2, $2 FROM users
+        WHERE activated = true AND deleted_at IS NULL
+            AND NOT EXISTS(SELECT 1 FROM email_digests WHERE email_digests.user_id = users.id)
+        ON CONFLICT (user_id) DO NOTHING",
+        DigestFrequency::DEFAULT.name(),
+        now()
+    )
+    .execute(&state.pool)
+    .await?;
+
+    let checked_at = now();
+
+    loop {
+        let mut transaction = state.pool.begin().await?;
+
+        // skip locked so several servers can share the work and nobody gets the same digest twice,
+        // and only users with something new are looked at so quiet accounts aren't written every run
+        let Some(digest) = sqlx::query!(
+            "SELECT user_id, frequency, covered_until FROM email_digests
+            JOIN users ON users.id = email_digests.user_id
+            WHERE frequency != $1 AND next_digest_at <= $2
+                AND users.activated = true AND users.deleted_at IS NULL
+                AND EXISTS(
+                    SELECT 1 FROM chat_messages
+                    WHERE recipient_id = email_digests.user_id AND kind = 'user' AND sent_at > covered_until
+                )
+            ORDER BY next_digest_at LIMIT 1 FOR UPDATE OF email_digests SKIP LOCKED",
+            DigestFrequency::Never.name(),
+            checked_at
+        )
+        .fetch_optional(&mut *transaction)
+        .await?
+        else {
+            return Ok(());
+        };
+
+        let period = DigestFrequency::from_name(&digest.frequency)
+            .and_then(|frequency| frequency.period())
+            .unwrap_or(time::Duration::days(1));
+        let covered_until = now() - AWAY_FOR;
+
+        let conversations = if state.online_users.is_online(digest.user_id) {
+            vec![]
+        } else {
+            unread_conversations(digest.user_id, digest.covered_until, covered_until, state).await?
+        };
+
+        let sent = if conversations.is_empty() {
+            false
+        } else {
+            match send_digest(digest.user_id, conversations, state).await {
+                Ok(()) => true,
+                Err(error) => {
+                    tracing::error!(
+                        "Failed to send email digest to user({}) with error ({error})",
+                        digest.user_id
+                    );
+                    false
+                }
+            }
+        };
+
+        let next_digest_at = if sent {
+            let next_digest_at = now() + period;
+
+            sqlx::query!(
+                "UPDATE email_digests SET covered_until = $1, next_digest_at = $2, last_sent_at = $3 WHERE user_id = $4",
+                covered_until,
+                next_digest_at,
+                now(),
+                digest.user_id
+            )
+            .execute(&mut *transaction)
+            .await?;
+
+            Some(next_digest_at)
+        } else {
+            // look again next time in case messages come in or the mail server is back
+            sqlx::query!(
+                "UPDATE email_digests SET next_digest_at = $1 WHERE user_id = $2",
+                checked_at + CHECK_INTERVAL,
+                digest.user_id
+            )
+            .execute(&mut *transaction)
+            .await?;
+
+            None
+        };
+
+        transaction.commit().await?;
+
+        if let Some(next_digest_at) = next_digest_at {
+            tracing::info!(
+                "sent email digest to user({}), next one at {next_digest_at} at the earliest",
+                digest.user_id
+            );
+        }
+    }
+}
+
+/// Messages from each person that the user hasn't read and that weren't in an earlier digest.
+async fn unread_conversations(
+    user_id: i32,
+    from: time::PrimitiveDateTime,
+    until: time::PrimitiveDateTime,
+    state: &AppState,
+) -> anyhow::Result<Vec<UnreadConversation>> {
+    Ok(sqlx::query!(
+        r#"SELECT users.username, users.display_name, users.profile_picture_key, COUNT(*) AS "count!"
+        FROM chat_messages
+        JOIN users ON users.id = chat_messages.sender_id
+        LEFT JOIN conversation_reads
+            ON conversation_reads.user_id = $1 AND conversation_reads.other_user_id = chat_messages.sender_id
+        WHERE chat_messages.recipient_id = $1 AND chat_messages.kind = 'user'
+            AND chat_messages.sent_at > $2 AND chat_messages.sent_at <= $3
+            AND (conversation_reads.last_read_at IS NULL OR chat_messages.sent_at > conversation_reads.last_read_at)
+            AND NOT EXISTS(
+                SELECT 1 FROM conversation_settings
+                WHERE conversation_settings.user_id = $1 AND conversation_settings.other_user_id = chat_messages.sender_id
+                    AND conversation_settings.muted
+                    AND (conversation_settings.muted_until IS NULL OR conversation_settings.muted_until > $4)
+            )
+            AND NOT EXISTS(
+                SELECT 1 FROM blocked_users
+                WHERE (user_id = $1 AND blocked_id = chat_messages.sender_id)
+                    OR (user_id = chat_messages.sender_id AND blocked_id = $1)
+            )
+        GROUP BY users.id
+        ORDER BY COUNT(*) DESC, users.username"#,
+        user_id,
+        from,
+        until,
+        now()
+    )
+    .fetch_all(&state.pool)
+    .await?
+    .into_iter()
+    .map(|rec| UnreadConversation {
+        sender: Username::new(rec.username, rec.display_name, rec.profile_picture_key),
+        count: rec.count,
+    })
+    .collect())
+}
+
+async fn send_digest(
+    user_id: i32,
+    conversations: Vec<UnreadConversation>,
+    state: &AppState,
+) -> anyhow::Result<()> {
+    let email_addr = sqlx::query!("SELECT email FROM users WHERE id = $1", user_id)
+        .fetch_one(&state.pool)
+        .await?
+        .email;
+
+    let template = DigestEmailTemplate {
+        username: Username::new_from_id(user_id, &state.pool).await?,
+        conversations,
+        unsubscribe_token: unsubscribe_token(user_id, &state.jws_key)?,
+    };
+
+    let unsubscribe_url = format!(
+        "http://localhost:3000/unsubscribe/digest/{}",
+        template.unsubscribe_token
+    );
+
+    let email = Message::builder()
+        .from("Hats Chat <josh.a.roo2004@gmail.com>".parse()?)
+        .to(email_addr.parse()?)
+        .subject(format!("You have {} unread messages", template.total()))
+        .header(ContentType::TEXT_HTML)
+        .header(ListUnsubscribe(format!("<{unsubscribe_url}>")))
+        .header(ListUnsubscribePost)
+        .body(template.render()?)?;
+
+    // the smtp transport blocks, keep it off the async workers
+    let mailer = state.mailer.clone();
+    tokio::task::spawn_blocking(move || mailer.send(&email)).await??;
+
+    Ok(())
+}
+
+/// Lets mail clients show their own unsubscribe button.
+#[derive(Clone)]
+struct ListUnsubscribe(String);
+
+impl Header for ListUnsubscribe {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self(s.to_owned()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), self.0.clone())
+    }
+}
+
+/// Marks the `List-Unsubscribe` link as safe to post to without asking (RFC 8058).
+#[derive(Clone)]
+struct ListUnsubscribePost;
+
+impl Header for ListUnsubscribePost {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe-Post")
+    }
+
+    fn parse(_s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self)
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), String::from("List-Unsubscribe=One-Click"))
+    }
+}
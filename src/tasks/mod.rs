@@ -0,0 +1,21 @@
+use crate::data::app_state::AppState;
+
+pub mod account_deletion;
+pub mod data_export;
+pub mod disappearing_messages;
+pub mod email_digest;
+pub mod scheduled_messages;
+
+/// Starts the jobs that run alongside the web server for as long as it is up.
+pub fn spawn_background_tasks(state: AppState) {
+    tokio::spawn(account_deletion::run(state.clone()));
+    tokio::spawn(data_export::resume_pending(state.clone()));
+    tokio::spawn(email_digest::run(state.clone()));
+    tokio::spawn(disappearing_messages::run(state.clone()));
+    tokio::spawn(scheduled_messages::run(state));
+}
+
+pub fn now() -> time::PrimitiveDateTime {
+    let utc = time::OffsetDateTime::now_utc();
+    time::PrimitiveDateTime::new(utc.date(), utc.time())
+}
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use crate::{
+    api::chat::{announce_message, insert_message, OutgoingMessage},
+    data::app_state::AppState,
+    utils::relationship::can_message,
+};
+
+use super::now;
+
+/// Scheduled messages go out at most this long after they are due.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+pub async fn run(state: AppState) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(error) = send_due_messages(&state).await {
+            tracing::error!("Failed to send scheduled messages with error ({error})");
+        }
+    }
+}
+
+async fn send_due_messages(state: &AppState) -> anyhow::Result<()> {
+    loop {
+        let mut transaction = state.pool.begin().await?;
+
+        // the row stays locked until the message is in the conversation so no other server sends it too
+        let Some(scheduled) = sqlx::query!(
+            "SELECT id, sender_id, recipient_id, msg FROM scheduled_messages
+            WHERE failed = false AND send_at <= $1
+            ORDER BY send_at, id LIMIT 1 FOR UPDATE SKIP LOCKED",
+            now()
+        )
+        .fetch_optional(&mut *transaction)
+        .await?
+        else {
+            return Ok(());
+        };
+
+        if !can_message(scheduled.sender_id, scheduled.recipient_id, &state.pool).await? {
+            // kept so the sender sees it didn't go out
+            sqlx::query!(
+                "UPDATE scheduled_messages SET failed = true WHERE id = $1",
+                scheduled.id
+            )
+            .execute(&mut *transaction)
+            .await?;
+
+            transaction.commit().await?;
+
+            tracing::debug!(
+                "scheduled message({}) can't be sent to user({})",
+                scheduled.id,
+                scheduled.recipient_id
+            );
+
+            continue;
+        }
+
+        let sent = insert_message(
+            OutgoingMessage {
+                sender_id: scheduled.sender_id,
+                recipient_id: scheduled.recipient_id,
+                msg: scheduled.msg,
+                attachments: vec![],
+            },
+            now(),
+            &mut transaction,
+        )
+        .await?;
+
+        sqlx::query!("DELETE FROM scheduled_messages WHERE id = $1", scheduled.id)
+            .execute(&mut *transaction)
+            .await?;
+
+        transaction.commit().await?;
+
+        tracing::debug!(
+            "sent scheduled message({}) as message({})",
+            scheduled.id,
+            sent.id
+        );
+
+        announce_message(state, &sent).await;
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::OnceLock,
+};
+
+use pulldown_cmark::{escape::escape_html, CodeBlockKind, Event, Options, Parser, Tag};
+use syntect::{
+    highlighting::ThemeSet,
+    html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+
+use super::{mentions::find_mentions, percent_encode};
+
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAX_SET: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAX_SET.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+/// Renders the markdown subset allowed in messages: bold, italics, strikethrough, inline code,
+/// fenced code blocks, links, block quotes, lists and line breaks. Anything else, including
+/// raw html, is shown as typed or dropped by the sanitizer so the output is safe to embed.
+/// Mentions of the `mentioned` usernames become links to their profiles.
+pub fn render_markdown(text: &str, mentioned: &[String]) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+
+    let mut events = vec![];
+    let mut code_block: Option<(String, String)> = None;
+    let mut link_depth = 0;
+
+    for event in coalesce_text(Parser::new_ext(text, options)) {
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(language) => language.to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                code_block = Some((language, String::new()));
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                if let Some((language, code)) = code_block.take() {
+                    events.push(Event::Html(highlight(&language, &code).into()));
+                }
+            }
+            Event::Text(text) if code_block.is_some() => {
+                if let Some((_, code)) = code_block.as_mut() {
+                    code.push_str(&text);
+                }
+            }
+            Event::Start(Tag::Link(..)) => {
+                link_depth += 1;
+                events.push(event);
+            }
+            Event::End(Tag::Link(..)) => {
+                link_depth -= 1;
+                events.push(event);
+            }
+            Event::Text(text) if link_depth == 0 => push_mentions(&mut events, &text, mentioned),
+            Event::Html(html) => events.push(Event::Text(html)),
+            // messages keep the line breaks they were typed with
+            Event::SoftBreak => events.push(Event::HardBreak),
+            event => events.push(event),
+        }
+    }
+
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events.into_iter());
+
+    sanitize(&html)
+}
+
+/// The parser can split text in the middle of a word, like at an underscore in a username.
+fn coalesce_text<'a>(parser: Parser<'a, '_>) -> Vec<Event<'a>> {
+    let mut events: Vec<Event<'a>> = vec![];
+
+    for event in parser {
+        match (events.last_mut(), event) {
+            (Some(Event::Text(previous)), Event::Text(text)) => {
+                *previous = format!("{previous}{text}").into();
+            }
+            (_, event) => events.push(event),
+        }
+    }
+
+    events
+}
+
+fn push_mentions<'a>(events: &mut Vec<Event<'a>>, text: &str, mentioned: &[String]) {
+    let mut rest = 0;
+
+    for (range, username) in find_mentions(text) {
+        if !mentioned.iter().any(|mentioned| mentioned == username) {
+            continue;
+        }
+
+        if range.start > rest {
+            events.push(Event::Text(text[rest..range.start].to_owned().into()));
+        }
+
+        let mut link = String::new();
+        let _ = escape_html(&mut link, username);
+        events.push(Event::Html(
+            format!(
+                "<a class=\"mention\" href=\"/account/{}\">@{link}</a>",
+                percent_encode(username)
+            )
+            .into(),
+        ));
+
+        rest = range.end;
+    }
+
+    if rest < text.len() {
+        events.push(Event::Text(text[rest..].to_owned().into()));
+    }
+}
+
+fn highlight(language: &str, code: &str) -> String {
+    let syntax_set = syntax_set();
+    let syntax = syntax_set
+        .find_syntax_by_token(language)
+        .unwrap_or_else(|| syntax_set.find_syntax_plain_text());
+
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, syntax_set, CLASS_STYLE);
+
+    for line in LinesWithEndings::from(code) {
+        if generator
+            .parse_html_for_line_which_includes_newline(line)
+            .is_err()
+        {
+            // fall back to an unhighlighted block
+            return format!("<pre><code>{}</code></pre>", ammonia::clean_text(code));
+        }
+    }
+
+    format!(
+        "<pre class=\"hl-code\"><code>{}</code></pre>",
+        generator.finalize()
+    )
+}
+
+fn sanitize(html: &str) -> String {
+    let tags = HashSet::from([
+        "p",
+        "br",
+        "strong",
+        "em",
+        "del",
+        "code",
+        "pre",
+        "a",
+        "blockquote",
+        "ul",
+        "ol",
+        "li",
+        "span",
+    ]);
+    let tag_attributes = HashMap::from([
+        ("a", HashSet::from(["href", "class"])),
+        ("span", HashSet::from(["class"])),
+        ("pre", HashSet::from(["class"])),
+    ]);
+
+    ammonia::Builder::default()
+        .tags(tags)
+        .tag_attributes(tag_attributes)
+        .generic_attributes(HashSet::new())
+        .url_schemes(HashSet::from(["http", "https", "mailto"]))
+        .link_rel(Some("noopener noreferrer nofollow"))
+        .clean(html)
+        .to_string()
+}
+
+/// Colors for highlighted code blocks, following the light or dark preference of the browser.
+pub fn highlight_css() -> &'static str {
+    static CSS: OnceLock<String> = OnceLock::new();
+
+    CSS.get_or_init(|| {
+        let themes = ThemeSet::load_defaults();
+
+        let light = css_for_theme_with_class_style(&themes.themes["InspiredGitHub"], CLASS_STYLE)
+            .unwrap_or_default();
+        let dark = css_for_theme_with_class_style(&themes.themes["base16-ocean.dark"], CLASS_STYLE)
+            .unwrap_or_default();
+
+        format!("{light}\n@media (prefers-color-scheme: dark) {{\n{dark}\n}}\n")
+    })
+}
@@ -0,0 +1,103 @@
+use std::ops::Range;
+
+fn is_username_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+/// Finds every `@username` in the text, returning the byte range of the whole mention and the username.
+/// An `@` in the middle of a word, like in an email address, is not a mention.
+pub fn find_mentions(text: &str) -> Vec<(Range<usize>, &str)> {
+    let mut mentions = vec![];
+    let mut previous = None;
+
+    for (start, c) in text.char_indices() {
+        let after_word = previous.is_some_and(is_username_char);
+        previous = Some(c);
+
+        if c != '@' || after_word {
+            continue;
+        }
+
+        let name_start = start + 1;
+        let name_end = text[name_start..]
+            .find(|c| !is_username_char(c))
+            .map_or(text.len(), |end| name_start + end);
+        // a mention at the end of a sentence
+        let name = text[name_start..name_end].trim_end_matches('.');
+
+        if !name.is_empty() {
+            mentions.push((start..name_start + name.len(), name));
+        }
+    }
+
+    mentions
+}
+
+/// The usernames mentioned in a message, each listed once.
+pub fn parse_mentions(text: &str) -> Vec<String> {
+    let mut usernames: Vec<String> = vec![];
+
+    for (_, username) in find_mentions(text) {
+        if !usernames.iter().any(|existing| existing == username) {
+            usernames.push(username.to_owned());
+        }
+    }
+
+    usernames
+}
+
+/// The unfinished mention at the end of the text being typed, if there is one.
+pub fn partial_mention(text: &str) -> Option<&str> {
+    let start = text.rfind('@')?;
+    let partial = &text[start + 1..];
+
+    let after_word = text[..start]
+        .chars()
+        .next_back()
+        .is_some_and(is_username_char);
+
+    (!after_word && partial.chars().all(is_username_char)).then_some(partial)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_mentions_with_their_ranges() {
+        let text = "hi @alice and @bob_2";
+
+        assert_eq!(
+            find_mentions(text),
+            vec![(3..9, "alice"), (14..20, "bob_2")]
+        );
+    }
+
+    #[test]
+    fn email_addresses_are_not_mentions() {
+        assert!(find_mentions("mail alice@example.com").is_empty());
+    }
+
+    #[test]
+    fn trailing_dots_are_not_part_of_the_name() {
+        assert_eq!(find_mentions("thanks @j.doe."), vec![(7..13, "j.doe")]);
+        assert!(find_mentions("just an @ sign").is_empty());
+    }
+
+    #[test]
+    fn usernames_are_listed_once() {
+        assert_eq!(
+            parse_mentions("@alice @bob @alice"),
+            vec![String::from("alice"), String::from("bob")]
+        );
+    }
+
+    #[test]
+    fn partial_mention_at_the_end() {
+        assert_eq!(partial_mention("hey @al"), Some("al"));
+        assert_eq!(partial_mention("hey @"), Some(""));
+        assert_eq!(partial_mention("hey @alice "), None);
+        assert_eq!(partial_mention("alice@exa"), None);
+        assert_eq!(partial_mention("no mention"), None);
+    }
+}
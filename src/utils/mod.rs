@@ -1,19 +1,76 @@
-use http::StatusCode;
+use http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use sqlx::PgPool;
 use std::fmt::Debug;
 
-pub mod username;
 pub mod auth_layer;
+pub mod markdown;
+pub mod mentions;
+pub mod relationship;
+pub mod relative_time;
+pub mod username;
 
 pub trait ToServerError<T, E> {
     fn server_error(self) -> Result<T, (StatusCode, String)>;
 }
 
-impl<T, E> ToServerError<T, E> for Result<T, E> 
+impl<T, E> ToServerError<T, E> for Result<T, E>
 where
-    E: Debug
+    E: Debug,
 {
     fn server_error(self) -> Result<T, (StatusCode, String)> {
         self.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:?}")))
     }
 }
 
+/// Headers telling htmx to reload the whole page.
+pub fn refresh() -> HeaderMap {
+    let mut headers = HeaderMap::default();
+
+    headers.insert(
+        HeaderName::from_static("hx-refresh"),
+        HeaderValue::from_static("true"),
+    );
+
+    headers
+}
+
+/// Looks up the id of the user named in a path, 404 if there is none.
+pub async fn find_user_id(username: &str, pool: &PgPool) -> Result<i32, (StatusCode, String)> {
+    Ok(
+        sqlx::query!("SELECT id FROM users WHERE username = $1", username)
+            .fetch_optional(pool)
+            .await
+            .server_error()?
+            .ok_or((StatusCode::NOT_FOUND, String::from("User Not Found")))?
+            .id,
+    )
+}
+
+/// Percent encodes everything but unreserved characters so the text is safe in urls and headers.
+pub fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unreserved_characters_are_kept() {
+        assert_eq!(percent_encode("Az09-._~"), "Az09-._~");
+    }
+
+    #[test]
+    fn everything_else_is_encoded() {
+        assert_eq!(percent_encode("a b/c?"), "a%20b%2Fc%3F");
+        assert_eq!(percent_encode("é"), "%C3%A9");
+        assert_eq!(percent_encode(""), "");
+    }
+}
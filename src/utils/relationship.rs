@@ -0,0 +1,77 @@
+use sqlx::PgPool;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relationship {
+    Stranger,
+    Friends,
+    RequestSent,
+    RequestReceived,
+    Blocked,
+    BlockedBy,
+}
+
+impl Relationship {
+    pub fn from_flags(
+        blocked: bool,
+        blocked_by: bool,
+        friends: bool,
+        request_sent: bool,
+        request_received: bool,
+    ) -> Self {
+        if blocked {
+            Self::Blocked
+        } else if blocked_by {
+            Self::BlockedBy
+        } else if friends {
+            Self::Friends
+        } else if request_received {
+            Self::RequestReceived
+        } else if request_sent {
+            Self::RequestSent
+        } else {
+            Self::Stranger
+        }
+    }
+
+    pub async fn between(user_id: i32, other_user_id: i32, pool: &PgPool) -> anyhow::Result<Self> {
+        let rec = sqlx::query!(
+            "SELECT
+                EXISTS(SELECT 1 FROM blocked_users WHERE user_id = $1 AND blocked_id = $2) AS blocked,
+                EXISTS(SELECT 1 FROM blocked_users WHERE user_id = $2 AND blocked_id = $1) AS blocked_by,
+                EXISTS(SELECT 1 FROM friends WHERE user_id = $1 AND friend_id = $2) AS friends,
+                EXISTS(SELECT 1 FROM friend_requests WHERE sender_id = $1 AND recipient_id = $2) AS request_sent,
+                EXISTS(SELECT 1 FROM friend_requests WHERE sender_id = $2 AND recipient_id = $1) AS request_received",
+            user_id,
+            other_user_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(Self::from_flags(
+            rec.blocked.unwrap_or_default(),
+            rec.blocked_by.unwrap_or_default(),
+            rec.friends.unwrap_or_default(),
+            rec.request_sent.unwrap_or_default(),
+            rec.request_received.unwrap_or_default(),
+        ))
+    }
+}
+
+pub async fn can_message(sender_id: i32, recipient_id: i32, pool: &PgPool) -> anyhow::Result<bool> {
+    let relationship = Relationship::between(sender_id, recipient_id, pool).await?;
+
+    match relationship {
+        Relationship::Blocked | Relationship::BlockedBy => Ok(false),
+        Relationship::Friends => Ok(true),
+        _ => {
+            let recipient = sqlx::query!(
+                r#"SELECT friends_only_messages, deleted_at IS NOT NULL AS "deleted!" FROM users WHERE id = $1"#,
+                recipient_id
+            )
+            .fetch_one(pool)
+            .await?;
+
+            Ok(!recipient.friends_only_messages && !recipient.deleted)
+        }
+    }
+}
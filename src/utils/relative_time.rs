@@ -0,0 +1,70 @@
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+/// Short human readable age of a utc timestamp, like "5m" or "3d".
+pub fn relative_time(timestamp: PrimitiveDateTime) -> String {
+    let now = OffsetDateTime::now_utc();
+    let elapsed = now - timestamp.assume_utc();
+
+    if elapsed.whole_minutes() < 1 {
+        String::from("now")
+    } else if elapsed.whole_hours() < 1 {
+        format!("{}m", elapsed.whole_minutes())
+    } else if elapsed.whole_days() < 1 {
+        format!("{}h", elapsed.whole_hours())
+    } else if elapsed.whole_days() < 7 {
+        format!("{}d", elapsed.whole_days())
+    } else if timestamp.year() == now.year() {
+        format!(
+            "{} {}",
+            &timestamp.month().to_string()[..3],
+            timestamp.day()
+        )
+    } else {
+        format!(
+            "{} {} {}",
+            &timestamp.month().to_string()[..3],
+            timestamp.day(),
+            timestamp.year()
+        )
+    }
+}
+
+/// The date of a utc timestamp written out, like "October 19, 2026".
+pub fn full_date(timestamp: PrimitiveDateTime) -> String {
+    format!(
+        "{} {}, {}",
+        timestamp.month(),
+        timestamp.day(),
+        timestamp.year()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{macros::datetime, Duration};
+
+    use super::*;
+
+    fn ago(duration: Duration) -> PrimitiveDateTime {
+        let timestamp = OffsetDateTime::now_utc() - duration;
+        PrimitiveDateTime::new(timestamp.date(), timestamp.time())
+    }
+
+    #[test]
+    fn recent_times_are_short() {
+        assert_eq!(relative_time(ago(Duration::seconds(30))), "now");
+        assert_eq!(relative_time(ago(Duration::minutes(5))), "5m");
+        assert_eq!(relative_time(ago(Duration::hours(3))), "3h");
+        assert_eq!(relative_time(ago(Duration::days(6))), "6d");
+    }
+
+    #[test]
+    fn old_times_show_the_date() {
+        assert_eq!(relative_time(datetime!(2021-03-09 12:00)), "Mar 9 2021");
+    }
+
+    #[test]
+    fn full_date_is_written_out() {
+        assert_eq!(full_date(datetime!(2026-10-19 08:30)), "October 19, 2026");
+    }
+}
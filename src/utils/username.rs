@@ -1,26 +1,40 @@
 use sqlx::PgPool;
 
-
-
+use crate::profile_pictures::profile_picture_url;
 
 #[derive(Debug, Clone)]
 pub struct Username {
     username: String,
-    display_name: Option<String>
+    display_name: Option<String>,
+    profile_picture_key: Option<String>,
 }
 
 impl Username {
-    pub fn new(username: String, display_name: Option<String>) -> Self {
+    pub fn new(
+        username: String,
+        display_name: Option<String>,
+        profile_picture_key: Option<String>,
+    ) -> Self {
         Self {
             username,
             display_name,
+            profile_picture_key,
         }
     }
 
     pub async fn new_from_id(user_id: i32, pool: &PgPool) -> anyhow::Result<Self> {
-        let rec = sqlx::query!("SELECT username, display_name FROM users WHERE id = $1", user_id).fetch_one(pool).await?;
-
-        Ok(Self::new(rec.username, rec.display_name))
+        let rec = sqlx::query!(
+            "SELECT username, display_name, profile_picture_key FROM users WHERE id = $1",
+            user_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(Self::new(
+            rec.username,
+            rec.display_name,
+            rec.profile_picture_key,
+        ))
     }
 
     pub fn username(&self) -> String {
@@ -30,4 +44,8 @@ impl Username {
     pub fn display_name(&self) -> String {
         self.display_name.clone().unwrap_or(self.username.clone())
     }
+
+    pub fn profile_picture(&self, size: u32) -> String {
+        profile_picture_url(&self.username, self.profile_picture_key.as_deref(), size)
+    }
 }